@@ -0,0 +1,73 @@
+//! Per-tick cost of collecting and filtering processes, by process count.
+//!
+//! Collection reads the benchmark's own process once per simulated process,
+//! so it measures what a refresh costs on a machine with that many processes.
+
+use criterion::{BatchSize, BenchmarkId, Criterion, criterion_group, criterion_main};
+use rustop::filter::{KernelView, ProcessFilter};
+use rustop::snapshot::UsageInfo;
+use rustop::source;
+use rustop::users::UserFilter;
+
+const PROCESS_COUNTS: &[usize] = &[100, 1_000, 5_000, 20_000];
+
+/// A process list of `count` entries with varied names, owners and usage
+fn synthetic_processes(count: usize) -> Vec<UsageInfo> {
+    (0..count)
+        .map(|i| UsageInfo {
+            pid: i as u32 + 1,
+            ppid: (i as u32) / 8,
+            uid: (i % 4) as u32 * 1000,
+            user: ["root", "alice", "bob", "carol"][i % 4].to_string(),
+            name: format!("worker-{}", i % 97),
+            state: 'S',
+            kernel: i % 50 == 0,
+            cpu: (i % 100) as f64 * 0.5,
+            mem: (i as u64 % 512) * 1_000_000,
+            start_time: 1_700_000_000 + i as u64,
+            ..UsageInfo::default()
+        })
+        .collect()
+}
+
+fn collect(c: &mut Criterion) {
+    let source = source::default_source();
+    let pid = std::process::id();
+    let mut group = c.benchmark_group("collect");
+
+    for &count in PROCESS_COUNTS {
+        group.bench_with_input(BenchmarkId::from_parameter(count), &count, |b, &count| {
+            b.iter(|| (0..count).filter_map(|_| source.read(pid)).count());
+        });
+    }
+
+    group.finish();
+}
+
+fn filter(c: &mut Criterion) {
+    let filter = ProcessFilter {
+        name: Some("worker".to_string()),
+        users: Some(UserFilter::parse("alice,!root")),
+        kernel: KernelView::UserlandOnly,
+        cpu_above: Some(1.0),
+        mem_above: Some(10_000_000),
+        ..ProcessFilter::default()
+    };
+    let mut group = c.benchmark_group("filter");
+
+    for &count in PROCESS_COUNTS {
+        let processes = synthetic_processes(count);
+        group.bench_with_input(BenchmarkId::from_parameter(count), &processes, |b, processes| {
+            b.iter_batched(
+                || processes.clone(),
+                |mut processes| filter.apply(&mut processes),
+                BatchSize::LargeInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, collect, filter);
+criterion_main!(benches);
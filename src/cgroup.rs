@@ -0,0 +1,256 @@
+//! Control group paths and the containers they reveal.
+//!
+//! Container runtimes place every container in a cgroup named after the
+//! container ID, so the ID can be recovered from `/proc/[pid]/cgroup` without
+//! talking to the runtime.
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+/// Length of a full container ID in hex digits
+const ID_LEN: usize = 64;
+
+/// Length of the short container ID shown by `docker ps` and friends
+pub const SHORT_ID_LEN: usize = 12;
+
+/// The runtime a container was recognized as belonging to
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Runtime {
+    Docker,
+    Containerd,
+    Podman,
+    Crio,
+    /// A Kubernetes pod whose cgroup path doesn't name the runtime
+    Kubernetes,
+}
+
+impl fmt::Display for Runtime {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Runtime::Docker => "docker",
+            Runtime::Containerd => "containerd",
+            Runtime::Podman => "podman",
+            Runtime::Crio => "cri-o",
+            Runtime::Kubernetes => "kubernetes",
+        };
+        f.write_str(name)
+    }
+}
+
+/// A container a process runs in
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Container {
+    pub runtime: Runtime,
+    /// Full ID in lowercase hex
+    pub id: String,
+}
+
+impl Container {
+    /// The first 12 digits of the ID, enough to tell containers apart; an ID
+    /// read back from a recording may be shorter and is then shown whole
+    pub fn short_id(&self) -> &str {
+        self.id.get(..SHORT_ID_LEN).unwrap_or(&self.id)
+    }
+}
+
+/// Unit name prefixes of containers placed by the systemd cgroup driver,
+/// like `docker-<id>.scope`
+const SCOPE_PREFIXES: &[(&str, Runtime)] = &[
+    ("docker-", Runtime::Docker),
+    ("cri-containerd-", Runtime::Containerd),
+    ("nerdctl-", Runtime::Containerd),
+    ("crio-", Runtime::Crio),
+    ("libpod-", Runtime::Podman),
+];
+
+/// The cgroup path that best describes where a process is placed, from the
+/// content of `/proc/[pid]/cgroup`.
+///
+/// Each line is `hierarchy:controllers:path`. The unified (v2) hierarchy is
+/// preferred, then the systemd one, then any other; in hybrid setups the
+/// unified hierarchy is often left at the root, so the root only counts if
+/// every hierarchy has it.
+pub fn primary_path(content: &str) -> String {
+    let preference = |controllers: &str| match controllers {
+        "" => 0,
+        "name=systemd" => 1,
+        _ => 2,
+    };
+
+    content
+        .lines()
+        .filter_map(|line| {
+            let mut parts = line.splitn(3, ':');
+            let _hierarchy = parts.next()?;
+            let controllers = parts.next()?;
+            let path = parts.next()?;
+            Some((preference(controllers), path))
+        })
+        .filter(|(_, path)| *path != "/")
+        .min_by_key(|(preference, _)| *preference)
+        .map_or_else(|| "/".to_string(), |(_, path)| path.to_string())
+}
+
+/// Recognize the container a cgroup path belongs to.
+///
+/// Understands the layouts of the systemd driver (`docker-<id>.scope`,
+/// `cri-containerd-<id>.scope`, `crio-<id>.scope`, `libpod-<id>.scope`),
+/// the systemd driver on cgroup v1 (`...slice:cri-containerd:<id>`) and the
+/// cgroupfs driver, which names the directory after the bare ID.
+pub fn container(path: &str) -> Option<Container> {
+    let segments: Vec<&str> = path.split('/').filter(|segment| !segment.is_empty()).collect();
+
+    // Nested cgroups below the container belong to it too, so look from the leaf up
+    segments.iter().enumerate().rev().find_map(|(index, segment)| {
+        let segment = segment.strip_suffix(".scope").unwrap_or(segment);
+
+        if let Some((runtime, id)) = SCOPE_PREFIXES
+            .iter()
+            .find_map(|(prefix, runtime)| Some((*runtime, segment.strip_prefix(prefix)?)))
+            && is_id(id)
+        {
+            return Some(Container {
+                runtime,
+                id: id.to_string(),
+            });
+        }
+
+        if let [_, runtime, id] = segment.split(':').collect::<Vec<_>>()[..]
+            && is_id(id)
+        {
+            let runtime = match runtime {
+                "docker" => Runtime::Docker,
+                "cri-containerd" => Runtime::Containerd,
+                "crio" => Runtime::Crio,
+                _ => Runtime::Kubernetes,
+            };
+            return Some(Container {
+                runtime,
+                id: id.to_string(),
+            });
+        }
+
+        if is_id(segment) {
+            let ancestors = &segments[..index];
+            let runtime = if ancestors.iter().any(|ancestor| ancestor.starts_with("kubepods")) {
+                Runtime::Kubernetes
+            } else if ancestors.last().is_some_and(|parent| *parent == "docker" || *parent == "moby") {
+                Runtime::Docker
+            } else if ancestors.last() == Some(&"libpod_parent") {
+                Runtime::Podman
+            } else {
+                // containerd groups bare IDs by namespace, like /default/<id>
+                Runtime::Containerd
+            };
+            return Some(Container {
+                runtime,
+                id: segment.to_string(),
+            });
+        }
+
+        None
+    })
+}
+
+/// Whether `text` is a full container ID
+fn is_id(text: &str) -> bool {
+    text.len() == ID_LEN && text.bytes().all(|byte| matches!(byte, b'0'..=b'9' | b'a'..=b'f'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ID: &str = "4f6c2b8e9a1d3c5b7e0f2a4c6e8b0d1f3a5c7e9b1d3f5a7c9e1b3d5f7a9c1e3b";
+
+    fn recognized(path: &str) -> Option<(Runtime, String)> {
+        container(path).map(|container| (container.runtime, container.id))
+    }
+
+    #[test]
+    fn recognizes_docker() {
+        let expected = Some((Runtime::Docker, ID.to_string()));
+        assert_eq!(recognized(&format!("/system.slice/docker-{}.scope", ID)), expected);
+        assert_eq!(recognized(&format!("/docker/{}", ID)), expected);
+        assert_eq!(recognized(&format!("/moby/{}", ID)), expected);
+    }
+
+    #[test]
+    fn recognizes_podman() {
+        let expected = Some((Runtime::Podman, ID.to_string()));
+        assert_eq!(
+            recognized(&format!("/user.slice/user-1000.slice/user@1000.service/user.slice/libpod-{}.scope", ID)),
+            expected
+        );
+        // The container's own cgroup namespace shows nested groups below it
+        assert_eq!(recognized(&format!("/machine.slice/libpod-{}.scope/container", ID)), expected);
+        assert_eq!(recognized(&format!("/libpod_parent/{}", ID)), expected);
+    }
+
+    #[test]
+    fn recognizes_containerd() {
+        let expected = Some((Runtime::Containerd, ID.to_string()));
+        assert_eq!(recognized(&format!("/system.slice/cri-containerd-{}.scope", ID)), expected);
+        assert_eq!(recognized(&format!("/nerdctl-{}.scope", ID)), expected);
+        assert_eq!(recognized(&format!("/default/{}", ID)), expected);
+    }
+
+    #[test]
+    fn recognizes_kubernetes() {
+        assert_eq!(
+            recognized(&format!("/kubepods/besteffort/pod0f1e2d3c-4b5a-6978-8796-a5b4c3d2e1f0/{}", ID)),
+            Some((Runtime::Kubernetes, ID.to_string()))
+        );
+        assert_eq!(
+            recognized(&format!(
+                "/kubepods.slice/kubepods-burstable.slice/kubepods-burstable-pod0f1e2d3c.slice/cri-containerd-{}.scope",
+                ID
+            )),
+            Some((Runtime::Containerd, ID.to_string()))
+        );
+        assert_eq!(
+            recognized(&format!("/kubepods.slice/kubepods-pod0f1e2d3c.slice:crio:{}", ID)),
+            Some((Runtime::Crio, ID.to_string()))
+        );
+        assert_eq!(
+            recognized(&format!("/kubepods.slice/kubepods-pod0f1e2d3c.slice:cri-o:{}", ID)),
+            Some((Runtime::Kubernetes, ID.to_string()))
+        );
+    }
+
+    #[test]
+    fn ignores_paths_outside_containers() {
+        assert_eq!(recognized("/"), None);
+        assert_eq!(recognized("/user.slice/user-1000.slice/session-2.scope"), None);
+        assert_eq!(recognized("/system.slice/docker.service"), None);
+        // Too short, or not hexadecimal
+        assert_eq!(recognized(&format!("/docker/{}", &ID[..63])), None);
+        assert_eq!(recognized(&format!("/docker/{}", ID.to_uppercase())), None);
+    }
+
+    #[test]
+    fn shortens_ids_like_docker_ps() {
+        let container = Container {
+            runtime: Runtime::Docker,
+            id: ID.to_string(),
+        };
+        assert_eq!(container.short_id(), "4f6c2b8e9a1d");
+    }
+
+    #[test]
+    fn prefers_the_unified_hierarchy() {
+        let hybrid = "12:cpu,cpuacct:/docker/abc\n1:name=systemd:/system.slice/docker-abc.scope\n0::/system.slice/docker-abc.scope\n";
+        assert_eq!(primary_path(hybrid), "/system.slice/docker-abc.scope");
+
+        // The unified hierarchy left at the root loses to a placed one
+        let hybrid_root = "4:memory:/user.slice\n1:name=systemd:/user.slice/session-2.scope\n0::/\n";
+        assert_eq!(primary_path(hybrid_root), "/user.slice/session-2.scope");
+
+        let legacy = "4:memory:/docker/abc\n2:cpu:/docker/abc\n";
+        assert_eq!(primary_path(legacy), "/docker/abc");
+
+        assert_eq!(primary_path("0::/\n"), "/");
+        assert_eq!(primary_path(""), "/");
+    }
+}
@@ -0,0 +1,366 @@
+//! Turning consecutive readings of the process list into CPU usage and I/O rates.
+
+use crate::snapshot::UsageInfo;
+use crate::source::ProcessSource;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+/// What 100% CPU stands for
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CpuMode {
+    /// One fully used core; a process can go up to 100% times the number of cores
+    Irix,
+    /// All cores of the machine together
+    #[default]
+    Solaris,
+}
+
+impl CpuMode {
+    /// The other mode
+    pub fn toggled(self) -> Self {
+        match self {
+            CpuMode::Irix => CpuMode::Solaris,
+            CpuMode::Solaris => CpuMode::Irix,
+        }
+    }
+
+    /// What usage measured in this mode is multiplied by to express it in `mode`
+    pub fn factor(self, mode: CpuMode, num_cpus: f64) -> f64 {
+        match (self, mode) {
+            (CpuMode::Solaris, CpuMode::Irix) => num_cpus,
+            (CpuMode::Irix, CpuMode::Solaris) => 1.0 / num_cpus,
+            _ => 1.0,
+        }
+    }
+}
+
+/// What is kept of a process between two ticks
+struct Previous {
+    start_time: u64,
+    cpu_time: f64,
+    read_bytes: u64,
+    write_bytes: u64,
+    /// When the process was read, on the monotonic clock
+    sampled_at: Option<Instant>,
+}
+
+/// Counters of a process that has not been seen before, which started from zero
+const NEW_PROCESS: Previous = Previous {
+    start_time: 0,
+    cpu_time: 0.0,
+    read_bytes: 0,
+    write_bytes: 0,
+    sampled_at: None,
+};
+
+/// Counters of every row from the previous tick, keyed by process and row ID
+type History = HashMap<(u32, u32), Previous>;
+
+/// Reads every process once per tick and computes CPU usage and I/O rates
+/// from the difference to the previous tick.
+///
+/// Each tick costs a single pass over the process list, so the refresh
+/// period is the interval between ticks rather than the interval plus a
+/// second collection pass. Every process is timed from its own reading, so
+/// a long pass over thousands of processes doesn't skew the ones read last.
+/// Threads are measured the same way, with a history of their own.
+pub struct Collector {
+    source: Box<dyn ProcessSource>,
+    num_cpus: f64,
+    mode: CpuMode,
+    /// Whether to read the memory breakdown of every row
+    memory_breakdown: bool,
+    processes: History,
+    threads: History,
+}
+
+impl Collector {
+    pub fn new(source: Box<dyn ProcessSource>, num_cpus: usize, mode: CpuMode) -> Self {
+        Self {
+            source,
+            num_cpus: num_cpus.max(1) as f64,
+            mode,
+            memory_breakdown: false,
+            processes: History::new(),
+            threads: History::new(),
+        }
+    }
+
+    /// Where processes are read from, for reading more about a single one
+    pub fn source(&self) -> &dyn ProcessSource {
+        self.source.as_ref()
+    }
+
+    /// Read the memory breakdown (PSS, USS, shared and swap) along with the
+    /// rest from the next collection on, or stop reading it
+    pub fn set_memory_breakdown(&mut self, enabled: bool) {
+        self.memory_breakdown = enabled;
+    }
+
+    pub fn mode(&self) -> CpuMode {
+        self.mode
+    }
+
+    /// Switch the CPU mode and convert the usage in `processes`, read in the old mode
+    pub fn set_mode(&mut self, mode: CpuMode, processes: &mut [UsageInfo]) {
+        if mode == self.mode {
+            return;
+        }
+
+        let factor = self.mode.factor(mode, self.num_cpus);
+        for process in processes {
+            process.cpu *= factor;
+        }
+        self.mode = mode;
+    }
+
+    /// Read all processes and fill in their CPU usage and I/O rates.
+    ///
+    /// Rates are measured since the previous call. Processes seen for the first
+    /// time, which includes every process on the first call, get their
+    /// average since they started, so the first frame can be shown right away.
+    pub fn collect(&mut self) -> Vec<UsageInfo> {
+        let mut history = History::new();
+        let mut processes = Vec::new();
+
+        for pid in self.source.pids() {
+            if let Some(mut process) = self.source.read(pid) {
+                let sampled_at = Instant::now();
+                if self.memory_breakdown {
+                    self.source.read_memory_breakdown(&mut process);
+                }
+                processes.push(self.measure(process, sampled_at, &self.processes, &mut history));
+            }
+        }
+
+        self.processes = history;
+        processes
+    }
+
+    /// Read the threads of `pid`, or of every process when `None`, and fill in
+    /// their CPU usage and I/O rates like `collect` does for processes
+    pub fn collect_threads(&mut self, pid: Option<u32>) -> Vec<UsageInfo> {
+        let pids = match pid {
+            Some(pid) => vec![pid],
+            None => self.source.pids(),
+        };
+        let mut history = History::new();
+        let mut threads = Vec::new();
+
+        for pid in pids {
+            let mut rows = self.source.read_threads(pid);
+            let sampled_at = Instant::now();
+            // Threads share the memory of their process, so it is read once
+            if self.memory_breakdown
+                && let Some((first, rest)) = rows.split_first_mut()
+            {
+                self.source.read_memory_breakdown(first);
+                for thread in rest {
+                    thread.pss = first.pss;
+                    thread.uss = first.uss;
+                    thread.mapped_shared = first.mapped_shared;
+                    thread.swap = first.swap;
+                }
+            }
+            for thread in rows {
+                threads.push(self.measure(thread, sampled_at, &self.threads, &mut history));
+            }
+        }
+
+        self.threads = history;
+        threads
+    }
+
+    /// Fill in the rates of `row`, read at `sampled_at`, from its entry in
+    /// `previous`, and record its counters in `current` for the next tick
+    fn measure(
+        &self,
+        mut row: UsageInfo,
+        sampled_at: Instant,
+        previous: &History,
+        current: &mut History,
+    ) -> UsageInfo {
+        let key = (row.tgid, row.pid);
+        row.sampled_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |now| now.as_secs());
+
+        // A reused ID belongs to a different process with its own start time,
+        // and a new process is measured from its start with zeroed counters
+        let previous = previous
+            .get(&key)
+            .filter(|previous| previous.start_time == row.start_time);
+        let interval = match previous.and_then(|previous| previous.sampled_at) {
+            Some(previous_at) => sampled_at.duration_since(previous_at).as_secs_f64(),
+            None => row.sampled_at.saturating_sub(row.start_time) as f64,
+        };
+        let baseline = previous.unwrap_or(&NEW_PROCESS);
+        let per_second = |used: f64| {
+            if interval > 0.0 { used.max(0.0) / interval } else { 0.0 }
+        };
+        let scale = match self.mode {
+            CpuMode::Irix => 100.0,
+            CpuMode::Solaris => 100.0 / self.num_cpus,
+        };
+
+        row.cpu = per_second(row.cpu_time - baseline.cpu_time) * scale;
+        row.read_rate = per_second(row.read_bytes.saturating_sub(baseline.read_bytes) as f64);
+        row.write_rate = per_second(row.write_bytes.saturating_sub(baseline.write_bytes) as f64);
+
+        let counters = Previous {
+            start_time: row.start_time,
+            cpu_time: row.cpu_time,
+            read_bytes: row.read_bytes,
+            write_bytes: row.write_bytes,
+            sampled_at: Some(sampled_at),
+        };
+        current.insert(key, counters);
+        row
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::details::ProcessDetails;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    /// Rows the test changes between collections, shared with the collector
+    type Rows = Rc<RefCell<Vec<UsageInfo>>>;
+
+    /// A source that returns whatever the test put in `rows`: processes are
+    /// the rows whose `pid` is their `tgid`, threads every row of a `tgid`
+    struct StubSource {
+        rows: Rows,
+    }
+
+    impl ProcessSource for StubSource {
+        fn pids(&self) -> Vec<u32> {
+            let rows = self.rows.borrow();
+            rows.iter().filter(|row| row.pid == row.tgid).map(|row| row.pid).collect()
+        }
+
+        fn read(&self, pid: u32) -> Option<UsageInfo> {
+            self.rows.borrow().iter().find(|row| row.pid == pid && row.tgid == pid).cloned()
+        }
+
+        fn read_threads(&self, pid: u32) -> Vec<UsageInfo> {
+            self.rows.borrow().iter().filter(|row| row.tgid == pid).cloned().collect()
+        }
+
+        fn read_memory_breakdown(&self, _process: &mut UsageInfo) {}
+
+        fn details(&self, _pid: u32) -> ProcessDetails {
+            ProcessDetails::default()
+        }
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn row(tgid: u32, pid: u32, start_time: u64, cpu_time: f64) -> UsageInfo {
+        UsageInfo {
+            pid,
+            tgid,
+            start_time,
+            cpu_time,
+            ..UsageInfo::default()
+        }
+    }
+
+    fn collector(rows: &[UsageInfo], num_cpus: usize, mode: CpuMode) -> (Collector, Rows) {
+        let rows = Rc::new(RefCell::new(rows.to_vec()));
+        let source = StubSource { rows: Rc::clone(&rows) };
+        (Collector::new(Box::new(source), num_cpus, mode), rows)
+    }
+
+    fn cpu_of(rows: &[UsageInfo], pid: u32) -> f64 {
+        rows.iter().find(|row| row.pid == pid).unwrap().cpu
+    }
+
+    #[test]
+    fn measures_the_delta_over_the_interval() {
+        let started = now() - 100;
+        let (mut collector, rows) = collector(&[row(1, 1, started, 10.0)], 4, CpuMode::Irix);
+        collector.collect();
+
+        // Half a core's worth of CPU time over at least 200ms
+        thread::sleep(Duration::from_millis(200));
+        rows.borrow_mut()[0].cpu_time = 10.1;
+        rows.borrow_mut()[0].read_bytes = 4096;
+        let processes = collector.collect();
+
+        let cpu = cpu_of(&processes, 1);
+        assert!(cpu > 25.0 && cpu <= 50.0, "cpu is {}", cpu);
+        let read_rate = processes[0].read_rate;
+        assert!(read_rate > 10_000.0 && read_rate <= 20_480.0, "read rate is {}", read_rate);
+    }
+
+    #[test]
+    fn averages_new_processes_over_their_lifetime() {
+        let started = now() - 10;
+        let (mut collector, rows) = collector(&[row(1, 1, started, 5.0)], 4, CpuMode::Irix);
+
+        // 5s of CPU over the 10s (or 11s past a second boundary) since it started
+        let first = collector.collect();
+        let cpu = cpu_of(&first, 1);
+        assert!((45.0..=50.0).contains(&cpu), "cpu is {}", cpu);
+
+        // One appearing between samples, and a reused PID, start from zero too
+        rows.borrow_mut().push(row(2, 2, started, 2.0));
+        rows.borrow_mut()[0] = row(1, 1, started + 5, 1.0);
+        let second = collector.collect();
+        let cpu = cpu_of(&second, 2);
+        assert!((18.0..=20.0).contains(&cpu), "cpu is {}", cpu);
+        let cpu = cpu_of(&second, 1);
+        assert!((16.0..=20.0).contains(&cpu), "cpu is {}", cpu);
+    }
+
+    #[test]
+    fn keeps_threads_apart_by_process_and_thread_id() {
+        let started = now() - 100;
+        let (mut collector, rows) = collector(
+            &[row(1, 1, started, 1.0), row(1, 7, started, 50.0), row(2, 2, started, 1.0)],
+            4,
+            CpuMode::Irix,
+        );
+        collector.collect_threads(None);
+
+        // Thread 7 now belongs to process 2; its old counters must not be used
+        rows.borrow_mut()[1].tgid = 2;
+        rows.borrow_mut()[1].cpu_time = 60.0;
+        let threads = collector.collect_threads(None);
+        let cpu = cpu_of(&threads, 7);
+        assert!((58.0..=60.0).contains(&cpu), "cpu is {}", cpu);
+
+        // Process 1 did nothing since the last reading
+        assert_eq!(cpu_of(&threads, 1), 0.0);
+    }
+
+    #[test]
+    fn solaris_mode_divides_by_the_cores() {
+        let started = now() - 1_000;
+        let processes = [row(1, 1, started, 800.0)];
+        let irix = cpu_of(&collector(&processes, 4, CpuMode::Irix).0.collect(), 1);
+        let solaris = cpu_of(&collector(&processes, 4, CpuMode::Solaris).0.collect(), 1);
+        // The two may be read on either side of a second boundary
+        assert!((irix / 4.0 - solaris).abs() < 0.1, "irix {} and solaris {}", irix, solaris);
+
+        assert_eq!(CpuMode::Irix.factor(CpuMode::Solaris, 4.0), 0.25);
+        assert_eq!(CpuMode::Solaris.factor(CpuMode::Irix, 4.0), 4.0);
+        assert_eq!(CpuMode::Irix.factor(CpuMode::Irix, 4.0), 1.0);
+
+        let (mut collector, _) = collector(&processes, 4, CpuMode::Irix);
+        let mut rows = collector.collect();
+        collector.set_mode(CpuMode::Solaris, &mut rows);
+        assert_eq!(collector.mode(), CpuMode::Solaris);
+        assert!((rows[0].cpu - irix / 4.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,553 @@
+//! The registry of process table columns.
+//!
+//! Every column knows its header, default width and alignment, how to
+//! extract its value from a `UsageInfo` and how to display it. The table,
+//! sorting and the machine-readable outputs are all driven from here.
+
+use crate::format::{
+    FormatOptions, format_cpu_time, format_duration, format_memory, format_time,
+};
+use crate::snapshot::UsageInfo;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub enum Column {
+    Pid,
+    Tid,
+    Tgid,
+    Ppid,
+    User,
+    State,
+    Nice,
+    Priority,
+    Threads,
+    Count,
+    Command,
+    Cpu,
+    Memory,
+    Virt,
+    Rss,
+    Shr,
+    Pss,
+    Uss,
+    Shared,
+    Swap,
+    CpuTime,
+    TimePlus,
+    StartTime,
+    Elapsed,
+    Io,
+    ReadRate,
+    WriteRate,
+    ReadTotal,
+    WriteTotal,
+    ReadSyscalls,
+    WriteSyscalls,
+    Container,
+    Cgroup,
+    Cmdline,
+    Exe,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// The raw value of a column for one process
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum Value {
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    Text(String),
+}
+
+impl Value {
+    /// Compare two values of the same column; floats use a total order so NaN never panics
+    pub fn compare(&self, other: &Value) -> Ordering {
+        match (self, other) {
+            (Value::Int(a), Value::Int(b)) => a.cmp(b),
+            (Value::UInt(a), Value::UInt(b)) => a.cmp(b),
+            (Value::Float(a), Value::Float(b)) => a.total_cmp(b),
+            (Value::Text(a), Value::Text(b)) => a.cmp(b),
+            _ => Ordering::Equal,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Int(value) => write!(f, "{}", value),
+            Value::UInt(value) => write!(f, "{}", value),
+            Value::Float(value) => write!(f, "{:.2}", value),
+            Value::Text(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Column {
+    /// Every column, in the order they are listed in help texts
+    pub const ALL: &'static [Column] = &[
+        Column::Pid,
+        Column::Tid,
+        Column::Tgid,
+        Column::Ppid,
+        Column::User,
+        Column::State,
+        Column::Nice,
+        Column::Priority,
+        Column::Threads,
+        Column::Count,
+        Column::Command,
+        Column::Cpu,
+        Column::Memory,
+        Column::Virt,
+        Column::Rss,
+        Column::Shr,
+        Column::Pss,
+        Column::Uss,
+        Column::Shared,
+        Column::Swap,
+        Column::CpuTime,
+        Column::TimePlus,
+        Column::StartTime,
+        Column::Elapsed,
+        Column::Io,
+        Column::ReadRate,
+        Column::WriteRate,
+        Column::ReadTotal,
+        Column::WriteTotal,
+        Column::ReadSyscalls,
+        Column::WriteSyscalls,
+        Column::Container,
+        Column::Cgroup,
+        Column::Cmdline,
+        Column::Exe,
+    ];
+
+    /// Columns shown when none are configured
+    pub const DEFAULT: &'static [Column] = &[
+        Column::Pid,
+        Column::User,
+        Column::Command,
+        Column::Cpu,
+        Column::Memory,
+        Column::StartTime,
+    ];
+
+    /// Name used on the command line, in the config file and as the key in exports
+    pub fn key(self) -> &'static str {
+        match self {
+            Column::Pid => "pid",
+            Column::Tid => "tid",
+            Column::Tgid => "tgid",
+            Column::Ppid => "ppid",
+            Column::User => "user",
+            Column::State => "state",
+            Column::Nice => "nice",
+            Column::Priority => "priority",
+            Column::Threads => "threads",
+            Column::Count => "count",
+            Column::Command => "command",
+            Column::Cpu => "cpu",
+            Column::Memory => "mem",
+            Column::Virt => "virt",
+            Column::Rss => "rss",
+            Column::Shr => "shr",
+            Column::Pss => "pss",
+            Column::Uss => "uss",
+            Column::Shared => "shared",
+            Column::Swap => "swap",
+            Column::CpuTime => "cputime",
+            Column::TimePlus => "time+",
+            Column::StartTime => "start",
+            Column::Elapsed => "elapsed",
+            Column::Io => "io",
+            Column::ReadRate => "read",
+            Column::WriteRate => "write",
+            Column::ReadTotal => "read_total",
+            Column::WriteTotal => "write_total",
+            Column::ReadSyscalls => "syscr",
+            Column::WriteSyscalls => "syscw",
+            Column::Container => "container",
+            Column::Cgroup => "cgroup",
+            Column::Cmdline => "cmdline",
+            Column::Exe => "exe",
+        }
+    }
+
+    /// Look a column up by its key or one of its aliases, ignoring case
+    pub fn from_key(key: &str) -> Option<Column> {
+        let key = key.to_lowercase();
+        let column = match key.as_str() {
+            "name" | "comm" => Column::Command,
+            "memory" => Column::Memory,
+            "pri" | "prio" => Column::Priority,
+            "lwp" | "spid" => Column::Tid,
+            "nlwp" => Column::Threads,
+            "vsz" => Column::Virt,
+            "time" | "cpu_time" => Column::CpuTime,
+            "timeplus" => Column::TimePlus,
+            "start_time" | "started" => Column::StartTime,
+            "etime" => Column::Elapsed,
+            "disk" => Column::Io,
+            "args" => Column::Cmdline,
+            "ctr" | "container_id" => Column::Container,
+            _ => return Column::ALL.iter().copied().find(|column| column.key() == key),
+        };
+        Some(column)
+    }
+
+    /// Whether the column holds text rather than a number
+    pub fn is_text(self) -> bool {
+        matches!(
+            self,
+            Column::User
+                | Column::State
+                | Column::Command
+                | Column::Container
+                | Column::Cgroup
+                | Column::Cmdline
+                | Column::Exe
+        )
+    }
+
+    /// Whether the column holds an amount of memory in bytes, or bytes per second
+    fn is_memory(self) -> bool {
+        matches!(
+            self,
+            Column::Memory
+                | Column::Virt
+                | Column::Rss
+                | Column::Shr
+                | Column::Pss
+                | Column::Uss
+                | Column::Shared
+                | Column::Swap
+                | Column::Io
+                | Column::ReadRate
+                | Column::WriteRate
+                | Column::ReadTotal
+                | Column::WriteTotal
+        )
+    }
+
+    /// Whether the column comes from the memory breakdown, which is costly to
+    /// read and only collected while something needs it
+    pub fn is_memory_breakdown(self) -> bool {
+        matches!(self, Column::Pss | Column::Uss | Column::Shared | Column::Swap)
+    }
+
+    pub fn header(self, options: &FormatOptions) -> String {
+        let header = match self {
+            Column::Pid => "PID",
+            Column::Tid => "TID",
+            Column::Tgid => "TGID",
+            Column::Ppid => "PPID",
+            Column::User => "USER",
+            Column::State => "S",
+            Column::Nice => "NI",
+            Column::Priority => "PRI",
+            Column::Threads => "THR",
+            Column::Count => "COUNT",
+            Column::Command => "COMMAND",
+            Column::Cpu => "CPU (%)",
+            Column::Memory => "MEMORY",
+            Column::Virt => "VIRT",
+            Column::Rss => "RSS",
+            Column::Shr => "SHR",
+            Column::Pss => "PSS",
+            Column::Uss => "USS",
+            Column::Shared => "SHARED",
+            Column::Swap => "SWAP",
+            Column::CpuTime => "CPU TIME",
+            Column::TimePlus => "TIME+",
+            Column::StartTime => "START TIME",
+            Column::Elapsed => "ELAPSED",
+            Column::Io => "IO/s",
+            Column::ReadRate => "READ/s",
+            Column::WriteRate => "WRITE/s",
+            Column::ReadTotal => "READ TOTAL",
+            Column::WriteTotal => "WRITE TOTAL",
+            Column::ReadSyscalls => "SYSCR",
+            Column::WriteSyscalls => "SYSCW",
+            Column::Container => "CONTAINER",
+            Column::Cgroup => "CGROUP",
+            Column::Cmdline => "CMDLINE",
+            Column::Exe => "EXE",
+        };
+
+        match options.fixed_unit() {
+            Some(unit) if self.is_memory() => {
+                format!("{} ({})", header, unit.label(options.units, options.compact))
+            }
+            _ => header.to_string(),
+        }
+    }
+
+    pub fn default_width(self) -> usize {
+        match self {
+            Column::Pid | Column::Tid | Column::Tgid | Column::Ppid => 6,
+            Column::User => 10,
+            Column::State => 1,
+            Column::Nice | Column::Priority => 3,
+            Column::Threads => 4,
+            Column::Count => 5,
+            Column::Command => 20,
+            Column::Cpu | Column::Virt | Column::Rss | Column::Shr => 10,
+            Column::Pss | Column::Uss | Column::Swap => 10,
+            Column::Shared => 11,
+            Column::Memory => 12,
+            Column::CpuTime | Column::TimePlus => 10,
+            Column::StartTime | Column::Elapsed => 12,
+            Column::Io | Column::ReadRate | Column::WriteRate => 10,
+            Column::ReadTotal | Column::WriteTotal => 11,
+            Column::ReadSyscalls | Column::WriteSyscalls => 10,
+            Column::Container => 12,
+            Column::Cgroup => 40,
+            Column::Cmdline => 50,
+            Column::Exe => 30,
+        }
+    }
+
+    pub fn default_align(self) -> Align {
+        match self {
+            Column::User
+            | Column::State
+            | Column::Command
+            | Column::Container
+            | Column::Cgroup
+            | Column::Cmdline
+            | Column::Exe => Align::Left,
+            _ => Align::Right,
+        }
+    }
+
+    /// Whether sorting by this column puts the largest values first unless asked otherwise
+    pub fn default_descending(self) -> bool {
+        matches!(
+            self,
+            Column::Threads
+                | Column::Count
+                | Column::Cpu
+                | Column::Memory
+                | Column::Virt
+                | Column::Rss
+                | Column::Shr
+                | Column::Pss
+                | Column::Uss
+                | Column::Shared
+                | Column::Swap
+                | Column::CpuTime
+                | Column::TimePlus
+                | Column::Elapsed
+                | Column::Io
+                | Column::ReadRate
+                | Column::WriteRate
+                | Column::ReadTotal
+                | Column::WriteTotal
+                | Column::ReadSyscalls
+                | Column::WriteSyscalls
+        )
+    }
+
+    /// The raw value used for sorting and machine-readable output
+    pub fn value(self, stat: &UsageInfo) -> Value {
+        match self {
+            // A thread row holds its thread ID where a process row holds the PID
+            Column::Pid | Column::Tid => Value::UInt(stat.pid.into()),
+            Column::Tgid => Value::UInt(stat.tgid.into()),
+            Column::Ppid => Value::UInt(stat.ppid.into()),
+            Column::User => Value::Text(stat.user.clone()),
+            Column::State => Value::Text(stat.state.to_string()),
+            Column::Nice => Value::Int(stat.nice.into()),
+            Column::Priority => Value::Int(stat.priority.into()),
+            Column::Threads => Value::UInt(stat.threads.into()),
+            Column::Count => Value::UInt(stat.count.into()),
+            Column::Command => Value::Text(stat.name.clone()),
+            Column::Cpu => Value::Float(stat.cpu),
+            Column::Memory | Column::Rss => Value::UInt(stat.mem),
+            Column::Virt => Value::UInt(stat.virt),
+            Column::Shr => Value::UInt(stat.shared),
+            Column::Pss => Value::UInt(stat.pss),
+            Column::Uss => Value::UInt(stat.uss),
+            Column::Shared => Value::UInt(stat.mapped_shared),
+            Column::Swap => Value::UInt(stat.swap),
+            Column::CpuTime | Column::TimePlus => Value::Float(stat.cpu_time),
+            Column::StartTime => Value::UInt(stat.start_time),
+            Column::Elapsed => Value::UInt(elapsed(stat)),
+            Column::Io => Value::UInt((stat.read_rate + stat.write_rate).round() as u64),
+            Column::ReadRate => Value::UInt(stat.read_rate.round() as u64),
+            Column::WriteRate => Value::UInt(stat.write_rate.round() as u64),
+            Column::ReadTotal => Value::UInt(stat.read_bytes),
+            Column::WriteTotal => Value::UInt(stat.write_bytes),
+            Column::ReadSyscalls => Value::UInt(stat.read_syscalls),
+            Column::WriteSyscalls => Value::UInt(stat.write_syscalls),
+            Column::Container => Value::Text(
+                stat.container
+                    .as_ref()
+                    .map_or_else(String::new, |container| container.short_id().to_string()),
+            ),
+            Column::Cgroup => Value::Text(stat.cgroup.clone()),
+            Column::Cmdline => Value::Text(stat.cmdline.clone()),
+            Column::Exe => Value::Text(stat.exe.clone()),
+        }
+    }
+
+    /// The value as exported: memory in the unit chosen with `options.unit`, or in bytes
+    pub fn export_value(self, stat: &UsageInfo, options: &FormatOptions) -> Value {
+        match self.value(stat) {
+            Value::UInt(bytes) if self.is_memory() => export_memory(bytes, options),
+            value => value,
+        }
+    }
+
+    /// Order two processes by this column, ascending
+    pub fn compare(self, a: &UsageInfo, b: &UsageInfo) -> Ordering {
+        self.value(a).compare(&self.value(b))
+    }
+
+    /// The value as shown in the table
+    pub fn display(self, stat: &UsageInfo, options: &FormatOptions) -> String {
+        match self {
+            Column::Cpu => format!("{:.2}%", stat.cpu),
+            Column::Memory | Column::Rss => format_memory(stat.mem, options),
+            Column::Virt => format_memory(stat.virt, options),
+            Column::Shr => format_memory(stat.shared, options),
+            Column::Pss | Column::Uss | Column::Shared | Column::Swap => match self.value(stat) {
+                Value::UInt(bytes) => format_memory(bytes, options),
+                value => value.to_string(),
+            },
+            Column::ReadTotal => format_memory(stat.read_bytes, options),
+            Column::WriteTotal => format_memory(stat.write_bytes, options),
+            Column::Io | Column::ReadRate | Column::WriteRate => match self.value(stat) {
+                Value::UInt(rate) => format_memory(rate, options),
+                value => value.to_string(),
+            },
+            Column::CpuTime => format_duration(stat.cpu_time as u64),
+            Column::TimePlus => format_cpu_time(stat.cpu_time),
+            Column::StartTime => format_time(stat.start_time, stat.sampled_at, options),
+            Column::Elapsed => format_duration(elapsed(stat)),
+            Column::Cmdline if stat.cmdline.is_empty() => format!("[{}]", stat.name),
+            column => column.value(stat).to_string(),
+        }
+    }
+}
+
+/// An amount of memory as exported, in bytes unless a unit was chosen explicitly
+pub fn export_memory(bytes: u64, options: &FormatOptions) -> Value {
+    match options.unit.map(|_| options.scaled(bytes).0) {
+        // Rounded to whole units, so keep it an integer like the byte count
+        Some(value) if value.fract() == 0.0 => Value::UInt(value as u64),
+        Some(value) => Value::Float(value),
+        None => Value::UInt(bytes),
+    }
+}
+
+/// Seconds from the start of the process until it was read
+fn elapsed(stat: &UsageInfo) -> u64 {
+    stat.sampled_at.saturating_sub(stat.start_time)
+}
+
+/// A column together with its configured width and alignment.
+///
+/// Written as `name[:width][:left|right]`, e.g. `command:30` or `user:8:right`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct ColumnSpec {
+    pub column: Column,
+    pub width: usize,
+    pub align: Align,
+}
+
+impl ColumnSpec {
+    /// Pad or truncate `text` to the width of this column
+    pub fn fit(&self, text: &str) -> String {
+        // Control characters (e.g. newlines in a command line) would break the table
+        let text: String = text
+            .chars()
+            .map(|c| if c.is_control() { ' ' } else { c })
+            .take(self.width)
+            .collect();
+        match self.align {
+            Align::Left => format!("{:<width$}", text, width = self.width),
+            Align::Right => format!("{:>width$}", text, width = self.width),
+        }
+    }
+}
+
+impl From<Column> for ColumnSpec {
+    fn from(column: Column) -> Self {
+        Self {
+            column,
+            width: column.default_width(),
+            align: column.default_align(),
+        }
+    }
+}
+
+impl FromStr for ColumnSpec {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let mut parts = spec.trim().split(':');
+        let name = parts.next().unwrap_or_default();
+        let column = Column::from_key(name).ok_or_else(|| {
+            let keys: Vec<&str> = Column::ALL.iter().map(|column| column.key()).collect();
+            format!("unknown column '{}', expected one of: {}", name, keys.join(", "))
+        })?;
+        let mut column_spec = ColumnSpec::from(column);
+
+        for part in parts {
+            match part.to_lowercase().as_str() {
+                "left" | "l" | "<" => column_spec.align = Align::Left,
+                "right" | "r" | ">" => column_spec.align = Align::Right,
+                width => {
+                    column_spec.width = width
+                        .parse()
+                        .map_err(|_| format!("invalid width or alignment '{}' in '{}'", part, spec))?;
+                }
+            }
+        }
+
+        Ok(column_spec)
+    }
+}
+
+impl fmt::Display for ColumnSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.column.key())?;
+        if self.width != self.column.default_width() {
+            write!(f, ":{}", self.width)?;
+        }
+        if self.align != self.column.default_align() {
+            let align = match self.align {
+                Align::Left => "left",
+                Align::Right => "right",
+            };
+            write!(f, ":{}", align)?;
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<String> for ColumnSpec {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<ColumnSpec> for String {
+    fn from(spec: ColumnSpec) -> Self {
+        spec.to_string()
+    }
+}
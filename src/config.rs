@@ -1,5 +1,11 @@
 use crate::SortBy;
-use dirs;
+use crate::collector::CpuMode;
+use crate::columns::ColumnSpec;
+use crate::filter::MemoryMetric;
+use crate::format::{Timezone, Unit, UnitSystem};
+use crate::group::GroupBy;
+use crate::size::Size;
+use crate::sort::SortKey;
 use serde::{Deserialize, Serialize};
 use std::fs;
 use std::io::{self, ErrorKind};
@@ -11,8 +17,17 @@ pub struct Config {
     /// Sort processes by CPU usage, memory usage, or PID
     pub sort_by: Option<SortBy>,
     
+    /// Filter expression, e.g. "name ~ /^cargo|rustc$/ && cpu > 20"
+    pub expr: Option<String>,
+
+    /// Sort by any columns as name[:asc|desc] in order of precedence; overrides sort_by
+    pub sort: Option<Vec<SortKey>>,
+
     /// Refresh rate in seconds
     pub refresh_rate: Option<f64>,
+
+    /// What 100% CPU stands for: one core ("irix") or the whole machine ("solaris")
+    pub cpu_mode: Option<CpuMode>,
     
     /// Show only the top N processes
     pub top: Option<usize>,
@@ -20,14 +35,36 @@ pub struct Config {
     /// Filter processes by name (case-insensitive)
     pub filter: Option<String>,
     
-    /// Show only processes owned by the specified user
+    /// Show only processes owned by these users: comma-separated names or UIDs,
+    /// prefix one with ! to hide it instead
     pub user: Option<String>,
     
-    /// Hide kernel processes
+    /// Show only processes in the container whose ID starts with this
+    pub container: Option<String>,
+    
+    /// Hide kernel threads
     pub no_kernel: Option<bool>,
+
+    /// Show only kernel threads
+    pub kernel_only: Option<bool>,
     
     /// Display memory in human-readable format (KB, MB, GB)
     pub human_readable: Option<bool>,
+
+    /// Count memory in powers of 1000 ("si") or 1024 ("iec")
+    pub units: Option<UnitSystem>,
+
+    /// Show all memory in this unit: "b", "k", "m", "g" or "t"
+    pub mem_unit: Option<Unit>,
+
+    /// Decimal places for memory values
+    pub precision: Option<usize>,
+
+    /// Short memory values like 1.2G
+    pub compact: Option<bool>,
+
+    /// Time zone for start times: "local", "utc" or an offset like "+02:00"
+    pub timezone: Option<Timezone>,
     
     /// Filter processes with CPU usage above this threshold (%)
     pub cpu_above: Option<f64>,
@@ -35,27 +72,63 @@ pub struct Config {
     /// Filter processes with CPU usage below this threshold (%)
     pub cpu_below: Option<f64>,
     
-    /// Filter processes with memory usage above this threshold (MB or in bytes if not human-readable)
-    pub mem_above: Option<u64>,
+    /// Filter processes with memory usage above this size, e.g. "512M" or "2GiB"
+    pub mem_above: Option<Size>,
     
-    /// Filter processes with memory usage below this threshold (MB or in bytes if not human-readable)
-    pub mem_below: Option<u64>,
+    /// Filter processes with memory usage below this size, e.g. "512M" or "2GiB"
+    pub mem_below: Option<Size>,
+
+    /// Which memory mem_above and mem_below compare: "rss", "pss", "uss", "shared",
+    /// "swap" or "virt"
+    pub mem_metric: Option<MemoryMetric>,
+
+    /// Show processes as a tree of parents and children
+    pub tree: Option<bool>,
+
+    /// In tree view, show CPU and memory summed over each process's subtree
+    pub tree_rollup: Option<bool>,
+
+    /// Show one row per group with CPU and memory summed: "name", "user", "ppid",
+    /// "cgroup" or "container"
+    pub group_by: Option<GroupBy>,
+
+    /// Hide the system summary (uptime, load, CPU cores, memory) above the table
+    pub no_summary: Option<bool>,
+
+    /// Columns to show as name[:width][:left|right], e.g. ["pid", "user", "command:30"]
+    pub columns: Option<Vec<ColumnSpec>>,
 }
 
 impl Default for Config {
     fn default() -> Self {
         Self {
             sort_by: Some(SortBy::Cpu),
+            sort: None,
+            expr: None,
             refresh_rate: Some(1.0),
+            cpu_mode: None,
             top: None,
             filter: None,
             user: None,
+            container: None,
             no_kernel: None,
+            kernel_only: None,
             human_readable: None,
+            units: None,
+            mem_unit: None,
+            precision: None,
+            compact: None,
+            timezone: None,
             cpu_above: None,
             cpu_below: None,
             mem_above: None,
             mem_below: None,
+            mem_metric: None,
+            tree: None,
+            tree_rollup: None,
+            group_by: None,
+            no_summary: None,
+            columns: None,
         }
     }
 }
@@ -127,12 +200,12 @@ pub fn get_config_path() -> Option<PathBuf> {
 
 /// Create a default config file if it doesn't exist
 pub fn ensure_config_file_exists() -> Result<(), io::Error> {
-    if let Some(config_path) = get_config_path() {
-        if !config_path.exists() {
-            let default_config = Config::default();
-            default_config.save_to_file(&config_path)?;
-            println!("Created default config file at: {:?}", config_path);
-        }
+    if let Some(config_path) = get_config_path()
+        && !config_path.exists()
+    {
+        let default_config = Config::default();
+        default_config.save_to_file(&config_path)?;
+        eprintln!("Created default config file at: {:?}", config_path);
     }
     Ok(())
 } 
\ No newline at end of file
@@ -0,0 +1,80 @@
+//! Details of a single process that are only read when it is inspected.
+//!
+//! Unlike `UsageInfo`, which is read for every process on every refresh, these
+//! are read for one process at a time, so they can afford directory listings
+//! and files that are expensive or large.
+
+use crate::snapshot::UsageInfo;
+use crate::source::ProcessSource;
+
+/// What the detail pane shows beyond the `UsageInfo` of a process.
+///
+/// Each part is `None` when it could not be read, usually because the process
+/// belongs to another user or the platform doesn't provide it.
+#[derive(Debug, Clone, Default)]
+pub struct ProcessDetails {
+    /// Current working directory
+    pub cwd: Option<String>,
+    /// Environment variables as `NAME=value`, as the process was started with
+    pub environment: Option<Vec<String>>,
+    /// Resource limits
+    pub limits: Option<Vec<Limit>>,
+    /// Open file descriptors, ordered by number
+    pub files: Option<Vec<OpenFile>>,
+    /// Control groups as listed in `/proc/[pid]/cgroup`, like `0::/user.slice`
+    pub cgroups: Option<Vec<String>>,
+    /// Namespaces, ordered by type
+    pub namespaces: Option<Vec<Namespace>>,
+}
+
+/// A resource limit with its soft and hard value as the kernel prints them
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Limit {
+    /// What is limited, like `Max open files`
+    pub name: String,
+    pub soft: String,
+    pub hard: String,
+    /// Unit of the values, empty for counts without one
+    pub units: String,
+}
+
+/// An open file descriptor and what it refers to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OpenFile {
+    pub fd: u32,
+    /// Path of the file, or a description like `socket:[12345]` or `pipe`
+    pub target: String,
+}
+
+/// A namespace the process is a member of
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Namespace {
+    /// Type of the namespace, like `net` or `pid`
+    pub kind: String,
+    /// Identity of the namespace, like `net:[4026531840]`; processes sharing
+    /// a namespace show the same value
+    pub id: String,
+}
+
+/// Most ancestors listed, far deeper than any real process tree
+const MAX_CHAIN: usize = 64;
+
+/// The ancestors of `process`, from its parent up to the first process.
+///
+/// Parents are read fresh from `source` rather than taken from the last sample,
+/// so the chain is complete even when filters or the thread view hide them.
+pub fn parent_chain(source: &dyn ProcessSource, process: &UsageInfo) -> Vec<UsageInfo> {
+    let mut chain = Vec::new();
+    let mut ppid = process.ppid;
+
+    // The first process has parent 0, which has no entry of its own; the
+    // length check guards against cycles from PIDs reused while walking
+    while ppid != 0 && chain.len() < MAX_CHAIN {
+        let Some(parent) = source.read(ppid) else {
+            break;
+        };
+        ppid = parent.ppid;
+        chain.push(parent);
+    }
+    chain
+}
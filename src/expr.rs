@@ -0,0 +1,548 @@
+//! The filter expression language.
+//!
+//! An expression compares columns against literals and combines the results,
+//! for example `name ~ /^cargo|rustc$/ && (cpu > 20 || mem > 1G) && user != root`.
+//!
+//! - Columns are named by their keys from the column registry.
+//! - Comparisons are `==` (or `=`), `!=`, `<`, `<=`, `>` and `>=`. `~` and `!~`
+//!   match a regex written as `/pattern/`, `/pattern/i` or a quoted string.
+//! - Conditions combine with `&&`, `||` and `!` (or `and`, `or` and `not`) and
+//!   group with parentheses. `!` binds tightest and `||` loosest.
+//! - Numbers may be negative and carry a size unit as in `--mem-above` (`K`,
+//!   `M`, `G`, `T` for powers of 1024, `KB`, `MB`, ... for powers of 1000) or a
+//!   trailing `%`. Text is a bare word or quoted with `"` or `'`.
+
+use crate::columns::{Column, Value};
+use crate::size;
+use crate::snapshot::UsageInfo;
+use regex::{Regex, RegexBuilder};
+use std::fmt;
+
+/// A parsed filter expression
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Or(Box<Expr>, Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    /// `column <op> literal`
+    Compare {
+        column: Column,
+        op: CompareOp,
+        literal: Literal,
+    },
+    /// `column ~ regex`, or `column !~ regex` when `negated`
+    Match {
+        column: Column,
+        regex: Regex,
+        negated: bool,
+    },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// The right-hand side of a comparison
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    /// A number with any size suffix applied; `text` is how it was written
+    Number { value: f64, text: String },
+    Text(String),
+}
+
+/// Why an expression could not be parsed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    /// Character offset into the expression where the problem was found
+    pub position: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position + 1)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+impl Expr {
+    /// Parse `input` into an expression
+    pub fn parse(input: &str) -> Result<Expr, ParseError> {
+        let tokens = tokenize(input)?;
+        let mut parser = Parser { tokens, next: 0 };
+        let expr = parser.or()?;
+
+        match parser.peek() {
+            (_, Token::End) => Ok(expr),
+            (position, token) => Err(ParseError {
+                position: *position,
+                message: format!("unexpected {}", token.describe()),
+            }),
+        }
+    }
+
+    /// Whether the expression looks at `column` anywhere
+    pub fn uses(&self, column: Column) -> bool {
+        match self {
+            Expr::Or(left, right) | Expr::And(left, right) => left.uses(column) || right.uses(column),
+            Expr::Not(inner) => inner.uses(column),
+            Expr::Compare { column: used, .. } | Expr::Match { column: used, .. } => *used == column,
+        }
+    }
+
+    /// Whether `stat` satisfies the expression
+    pub fn matches(&self, stat: &UsageInfo) -> bool {
+        match self {
+            Expr::Or(left, right) => left.matches(stat) || right.matches(stat),
+            Expr::And(left, right) => left.matches(stat) && right.matches(stat),
+            Expr::Not(inner) => !inner.matches(stat),
+            Expr::Compare { column, op, literal } => {
+                let ordering = match (column.value(stat), literal) {
+                    (Value::Text(value), Literal::Text(text)) => value.as_str().cmp(text.as_str()),
+                    (Value::Text(value), Literal::Number { text, .. }) => {
+                        value.as_str().cmp(text.as_str())
+                    }
+                    (value, Literal::Number { value: number, .. }) => {
+                        as_number(&value).total_cmp(number)
+                    }
+                    // Rejected by the parser, which knows the column types
+                    (_, Literal::Text(_)) => return false,
+                };
+                match op {
+                    CompareOp::Eq => ordering.is_eq(),
+                    CompareOp::Ne => ordering.is_ne(),
+                    CompareOp::Lt => ordering.is_lt(),
+                    CompareOp::Le => ordering.is_le(),
+                    CompareOp::Gt => ordering.is_gt(),
+                    CompareOp::Ge => ordering.is_ge(),
+                }
+            }
+            Expr::Match {
+                column,
+                regex,
+                negated,
+            } => {
+                let value = match column.value(stat) {
+                    Value::Text(text) => text,
+                    value => value.to_string(),
+                };
+                regex.is_match(&value) != *negated
+            }
+        }
+    }
+}
+
+fn as_number(value: &Value) -> f64 {
+    match value {
+        Value::Int(value) => *value as f64,
+        Value::UInt(value) => *value as f64,
+        Value::Float(value) => *value,
+        Value::Text(_) => f64::NAN,
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Number { value: f64, text: String },
+    Text(String),
+    Regex { pattern: String, ignore_case: bool },
+    Compare(CompareOp),
+    Match,
+    NotMatch,
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+    End,
+}
+
+impl Token {
+    /// How the token is referred to in error messages
+    fn describe(&self) -> String {
+        match self {
+            Token::Word(word) => format!("'{}'", word),
+            Token::Number { text, .. } => format!("number {}", text),
+            Token::Text(text) => format!("string \"{}\"", text),
+            Token::Regex { pattern, .. } => format!("regex /{}/", pattern),
+            Token::Compare(_) | Token::Match | Token::NotMatch => "operator".to_string(),
+            Token::And => "'&&'".to_string(),
+            Token::Or => "'||'".to_string(),
+            Token::Not => "'!'".to_string(),
+            Token::Open => "'('".to_string(),
+            Token::Close => "')'".to_string(),
+            Token::End => "end of expression".to_string(),
+        }
+    }
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    let error = |position: usize, message: String| ParseError { position, message };
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        let token = match c {
+            c if c.is_whitespace() => {
+                i += 1;
+                continue;
+            }
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Match,
+            '&' if next == Some('&') => Token::And,
+            '|' if next == Some('|') => Token::Or,
+            '&' | '|' => return Err(error(start, format!("expected '{}{}'", c, c))),
+            '!' if next == Some('=') => Token::Compare(CompareOp::Ne),
+            '!' if next == Some('~') => Token::NotMatch,
+            '!' => Token::Not,
+            '=' => Token::Compare(CompareOp::Eq),
+            '<' if next == Some('=') => Token::Compare(CompareOp::Le),
+            '<' => Token::Compare(CompareOp::Lt),
+            '>' if next == Some('=') => Token::Compare(CompareOp::Ge),
+            '>' => Token::Compare(CompareOp::Gt),
+            '"' | '\'' => {
+                let end = chars[i + 1..]
+                    .iter()
+                    .position(|&ch| ch == c)
+                    .ok_or_else(|| error(start, "unterminated string".to_string()))?;
+                let text: String = chars[i + 1..i + 1 + end].iter().collect();
+                i += end + 2;
+                tokens.push((start, Token::Text(text)));
+                continue;
+            }
+            '/' => {
+                let mut pattern = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err(error(start, "unterminated regex".to_string())),
+                        Some('/') => break,
+                        Some('\\') if chars.get(i + 1) == Some(&'/') => {
+                            pattern.push('/');
+                            i += 2;
+                        }
+                        Some(&ch) => {
+                            pattern.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                i += 1;
+                let ignore_case = chars.get(i) == Some(&'i');
+                if ignore_case {
+                    i += 1;
+                }
+                tokens.push((start, Token::Regex { pattern, ignore_case }));
+                continue;
+            }
+            c if c.is_ascii_digit()
+                || c == '.'
+                || (c == '-' && next.is_some_and(|n| n.is_ascii_digit() || n == '.')) =>
+            {
+                if c == '-' {
+                    i += 1;
+                }
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let digits: String = chars[start..i].iter().collect();
+                let suffix_start = i;
+                while i < chars.len() && chars[i].is_ascii_alphabetic() {
+                    i += 1;
+                }
+                let suffix: String = chars[suffix_start..i].iter().collect();
+                if chars.get(i) == Some(&'%') && suffix.is_empty() {
+                    i += 1;
+                }
+
+                let number: f64 = digits
+                    .parse()
+                    .map_err(|_| error(start, format!("invalid number '{}'", digits)))?;
+                let multiplier = size::multiplier(&suffix).ok_or_else(|| {
+                    error(suffix_start, format!("unknown size unit '{}'", suffix))
+                })?;
+                let text: String = chars[start..i].iter().collect();
+                tokens.push((start, Token::Number { value: number * multiplier as f64, text }));
+                continue;
+            }
+            c if c.is_alphanumeric() || c == '_' => {
+                while i < chars.len()
+                    && (chars[i].is_alphanumeric() || matches!(chars[i], '_' | '-' | '.' | '+'))
+                {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                let token = match word.to_lowercase().as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                };
+                tokens.push((start, token));
+                continue;
+            }
+            c => return Err(error(start, format!("unexpected character '{}'", c))),
+        };
+
+        // Every remaining token is one or two characters long
+        let two_chars = matches!(
+            (c, next),
+            ('&', Some('&'))
+                | ('|', Some('|'))
+                | ('!', Some('=' | '~'))
+                | ('<' | '>', Some('='))
+                | ('=', Some('='))
+        );
+        i += if two_chars { 2 } else { 1 };
+        tokens.push((start, token));
+    }
+
+    tokens.push((chars.len(), Token::End));
+    Ok(tokens)
+}
+
+/// Recursive descent over the tokens, one method per precedence level
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    next: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> &(usize, Token) {
+        &self.tokens[self.next]
+    }
+
+    fn advance(&mut self) -> (usize, Token) {
+        let token = self.tokens[self.next].clone();
+        if token.1 != Token::End {
+            self.next += 1;
+        }
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.and()?;
+        while self.peek().1 == Token::Or {
+            self.advance();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, ParseError> {
+        let mut expr = self.unary()?;
+        while self.peek().1 == Token::And {
+            self.advance();
+            expr = Expr::And(Box::new(expr), Box::new(self.unary()?));
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        match self.advance() {
+            (_, Token::Not) => Ok(Expr::Not(Box::new(self.unary()?))),
+            (position, Token::Open) => {
+                let expr = self.or()?;
+                match self.advance() {
+                    (_, Token::Close) => Ok(expr),
+                    (_, token) => Err(ParseError {
+                        position,
+                        message: format!("unclosed '(', found {}", token.describe()),
+                    }),
+                }
+            }
+            (position, Token::Word(name)) => self.comparison(position, &name),
+            (position, token) => Err(ParseError {
+                position,
+                message: format!("expected a column name, found {}", token.describe()),
+            }),
+        }
+    }
+
+    fn comparison(&mut self, position: usize, name: &str) -> Result<Expr, ParseError> {
+        let column = Column::from_key(name).ok_or_else(|| ParseError {
+            position,
+            message: format!("unknown column '{}'", name),
+        })?;
+
+        let (op_position, op) = self.advance();
+        let (value_position, value) = self.advance();
+
+        match op {
+            Token::Match | Token::NotMatch => {
+                let (pattern, ignore_case) = match value {
+                    Token::Regex { pattern, ignore_case } => (pattern, ignore_case),
+                    Token::Text(text) | Token::Word(text) => (text, false),
+                    token => {
+                        return Err(ParseError {
+                            position: value_position,
+                            message: format!("expected a regex, found {}", token.describe()),
+                        });
+                    }
+                };
+                let regex = RegexBuilder::new(&pattern)
+                    .case_insensitive(ignore_case)
+                    .build()
+                    .map_err(|e| {
+                        // The regex error spans several lines ending with the actual reason
+                        let reason = e.to_string();
+                        let reason = reason.lines().last().unwrap_or_default().trim();
+                        ParseError {
+                            position: value_position,
+                            message: format!(
+                                "invalid regex: {}",
+                                reason.trim_start_matches("error: ")
+                            ),
+                        }
+                    })?;
+                Ok(Expr::Match {
+                    column,
+                    regex,
+                    negated: op == Token::NotMatch,
+                })
+            }
+            Token::Compare(op) => {
+                let literal = match value {
+                    Token::Number { value, text } => Literal::Number { value, text },
+                    Token::Text(text) | Token::Word(text) if column.is_text() => {
+                        Literal::Text(text)
+                    }
+                    Token::Text(_) | Token::Word(_) => {
+                        return Err(ParseError {
+                            position: value_position,
+                            message: format!("column '{}' is numeric, expected a number", name),
+                        });
+                    }
+                    token => {
+                        return Err(ParseError {
+                            position: value_position,
+                            message: format!("expected a value, found {}", token.describe()),
+                        });
+                    }
+                };
+                Ok(Expr::Compare {
+                    column,
+                    op,
+                    literal,
+                })
+            }
+            token => Err(ParseError {
+                position: op_position,
+                message: format!(
+                    "expected a comparison after '{}', found {}",
+                    name,
+                    token.describe()
+                ),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(name: &str, user: &str, cpu: f64, mem: u64) -> UsageInfo {
+        UsageInfo {
+            pid: 100,
+            name: name.to_string(),
+            user: user.to_string(),
+            cpu,
+            mem,
+            ..UsageInfo::default()
+        }
+    }
+
+    fn matches(expr: &str, stat: &UsageInfo) -> bool {
+        Expr::parse(expr).unwrap().matches(stat)
+    }
+
+    #[test]
+    fn compares_numbers_with_units() {
+        let stat = process("cargo", "alice", 25.0, 2 * 1024 * 1024 * 1024);
+        assert!(matches("cpu > 20", &stat));
+        assert!(matches("cpu >= 25%", &stat));
+        assert!(!matches("cpu < 25", &stat));
+        assert!(matches("mem > 1G", &stat));
+        assert!(matches("mem == 2G", &stat));
+        assert!(!matches("mem > 3GB", &stat));
+    }
+
+    #[test]
+    fn compares_text_and_regexes() {
+        let stat = process("rustc", "alice", 0.0, 0);
+        assert!(matches("user == alice", &stat));
+        assert!(matches("user != 'root'", &stat));
+        assert!(matches("name ~ /^cargo|rustc$/", &stat));
+        assert!(matches("name ~ /RUST/i", &stat));
+        assert!(!matches("name ~ /RUST/", &stat));
+        assert!(matches("name !~ \"^cargo\"", &stat));
+    }
+
+    #[test]
+    fn accepts_negative_numbers_and_plus_in_columns() {
+        let stat = UsageInfo {
+            nice: -20,
+            cpu_time: 90.0,
+            ..UsageInfo::default()
+        };
+        assert!(matches("nice == -20", &stat));
+        assert!(matches("nice < -5", &stat));
+        assert!(matches("time+ > 1", &stat));
+        assert!(matches("time+>=90", &stat));
+    }
+
+    #[test]
+    fn or_binds_looser_than_and_and_not_tightest() {
+        let stat = process("cargo", "root", 50.0, 0);
+        // (cpu > 20 && user == alice) || name == cargo
+        assert!(matches("cpu > 20 && user == alice || name == cargo", &stat));
+        // cpu > 90 || (name == cargo && user == alice)
+        assert!(!matches("cpu > 90 || name == cargo && user == alice", &stat));
+        // (!(user == root)) || cpu > 20
+        assert!(matches("!user == root || cpu > 20", &stat));
+        assert!(!matches("!(user == root || cpu > 20)", &stat));
+        assert!(matches("not user == alice and (cpu > 90 or name == cargo)", &stat));
+    }
+
+    #[test]
+    fn reports_where_parsing_failed() {
+        let error = |expr: &str| Expr::parse(expr).unwrap_err();
+
+        assert_eq!(error("bogus > 1").message, "unknown column 'bogus'");
+        assert_eq!(error("bogus > 1").position, 0);
+        assert_eq!(error("cpu > high").message, "column 'cpu' is numeric, expected a number");
+        assert_eq!(error("cpu > high").position, 6);
+        assert_eq!(error("mem > 5X").message, "unknown size unit 'X'");
+        assert_eq!(error("(cpu > 1").message, "unclosed '(', found end of expression");
+        assert_eq!(error("cpu > 1 & mem > 1").message, "expected '&&'");
+        assert_eq!(error("name ~ /[/").message.split(':').next(), Some("invalid regex"));
+        assert_eq!(error("name ~ 'cargo").message, "unterminated string");
+        assert_eq!(error("cpu > 1 cpu").message, "unexpected 'cpu'");
+        assert_eq!(error("cpu").message, "expected a comparison after 'cpu', found end of expression");
+    }
+
+    #[test]
+    fn knows_the_columns_it_uses() {
+        let expr = Expr::parse("cpu > 1 || !(user == root && mem > 1M)").unwrap();
+        assert!(expr.uses(Column::Cpu));
+        assert!(expr.uses(Column::User));
+        assert!(expr.uses(Column::Memory));
+        assert!(!expr.uses(Column::Pss));
+    }
+}
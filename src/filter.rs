@@ -0,0 +1,133 @@
+//! Deciding which processes are shown.
+//!
+//! Filters only look at the fields of a `UsageInfo`, so everything they need
+//! has to be gathered by the collection pass up front.
+
+use crate::columns::Column;
+use crate::expr::Expr;
+use crate::snapshot::UsageInfo;
+use crate::users::UserFilter;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+/// Which side of the user/kernel split to show
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum KernelView {
+    #[default]
+    All,
+    /// Hide kernel threads
+    UserlandOnly,
+    /// Show only kernel threads
+    KernelOnly,
+}
+
+/// Which amount of memory the memory bounds apply to
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryMetric {
+    /// Resident memory, as in the MEMORY column
+    #[default]
+    Rss,
+    /// Resident memory with shared pages divided among the processes mapping them
+    Pss,
+    /// Resident memory no other process maps
+    Uss,
+    /// Resident memory also mapped by other processes
+    Shared,
+    /// Memory swapped out
+    Swap,
+    /// Virtual memory size
+    Virt,
+}
+
+impl MemoryMetric {
+    /// The column showing this amount
+    pub fn column(self) -> Column {
+        match self {
+            MemoryMetric::Rss => Column::Rss,
+            MemoryMetric::Pss => Column::Pss,
+            MemoryMetric::Uss => Column::Uss,
+            MemoryMetric::Shared => Column::Shared,
+            MemoryMetric::Swap => Column::Swap,
+            MemoryMetric::Virt => Column::Virt,
+        }
+    }
+
+    /// The amount of `stat` in bytes
+    fn bytes(self, stat: &UsageInfo) -> u64 {
+        match self {
+            MemoryMetric::Rss => stat.mem,
+            MemoryMetric::Pss => stat.pss,
+            MemoryMetric::Uss => stat.uss,
+            MemoryMetric::Shared => stat.mapped_shared,
+            MemoryMetric::Swap => stat.swap,
+            MemoryMetric::Virt => stat.virt,
+        }
+    }
+}
+
+/// Criteria a process has to meet to be shown; unset criteria match everything
+#[derive(Debug, Clone, Default)]
+pub struct ProcessFilter {
+    /// Lowercase substring the process name has to contain
+    pub name: Option<String>,
+    pub users: Option<UserFilter>,
+    pub kernel: KernelView,
+    /// Exclusive CPU bounds in percent
+    pub cpu_above: Option<f64>,
+    pub cpu_below: Option<f64>,
+    /// Exclusive memory bounds in bytes, on the amount picked by `mem_metric`
+    pub mem_above: Option<u64>,
+    pub mem_below: Option<u64>,
+    pub mem_metric: MemoryMetric,
+    /// Lowercase prefix of the ID of the container the process has to run in
+    pub container: Option<String>,
+    /// Filter expression checked on top of the other criteria
+    pub expr: Option<Expr>,
+}
+
+impl ProcessFilter {
+    /// Whether `stat` meets every criterion
+    pub fn matches(&self, stat: &UsageInfo) -> bool {
+        self.name
+            .as_ref()
+            .is_none_or(|name| stat.name.to_lowercase().contains(name))
+            && self
+                .users
+                .as_ref()
+                .is_none_or(|users| users.matches(stat.uid, &stat.user))
+            && match self.kernel {
+                KernelView::All => true,
+                KernelView::UserlandOnly => !stat.kernel,
+                KernelView::KernelOnly => stat.kernel,
+            }
+            && self.cpu_above.is_none_or(|threshold| stat.cpu > threshold)
+            && self.cpu_below.is_none_or(|threshold| stat.cpu < threshold)
+            && self.mem_above.is_none_or(|threshold| self.mem_metric.bytes(stat) > threshold)
+            && self.mem_below.is_none_or(|threshold| self.mem_metric.bytes(stat) < threshold)
+            && self.container.as_ref().is_none_or(|prefix| {
+                stat.container
+                    .as_ref()
+                    .is_some_and(|container| container.id.starts_with(prefix.as_str()))
+            })
+            && self.expr.as_ref().is_none_or(|expr| expr.matches(stat))
+    }
+
+    /// Move the CPU and memory bounds into a filter of their own, leaving the
+    /// criteria that pick processes by what they are rather than what they use
+    pub fn take_thresholds(&mut self) -> ProcessFilter {
+        ProcessFilter {
+            cpu_above: self.cpu_above.take(),
+            cpu_below: self.cpu_below.take(),
+            mem_above: self.mem_above.take(),
+            mem_below: self.mem_below.take(),
+            mem_metric: self.mem_metric,
+            ..ProcessFilter::default()
+        }
+    }
+
+    /// Drop every process from `stats` that doesn't match
+    pub fn apply(&self, stats: &mut Vec<UsageInfo>) {
+        stats.retain(|stat| self.matches(stat));
+    }
+}
@@ -0,0 +1,259 @@
+//! Formatting values for display.
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDateTime, TimeDelta, TimeZone, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Whether memory is counted in powers of 1000 or 1024
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum UnitSystem {
+    /// Powers of 1000: KB, MB, GB, TB
+    #[default]
+    Si,
+    /// Powers of 1024: KiB, MiB, GiB, TiB
+    Iec,
+}
+
+/// A memory unit, scaled according to the `UnitSystem`
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Unit {
+    B,
+    K,
+    M,
+    G,
+    T,
+}
+
+impl Unit {
+    const ALL: [Unit; 5] = [Unit::B, Unit::K, Unit::M, Unit::G, Unit::T];
+
+    /// Number of bytes in one of this unit
+    pub fn factor(self, system: UnitSystem) -> f64 {
+        let base: f64 = match system {
+            UnitSystem::Si => 1000.0,
+            UnitSystem::Iec => 1024.0,
+        };
+        base.powi(self as i32)
+    }
+
+    /// Label such as `MB` or `MiB`, or just `M` in the compact form
+    pub fn label(self, system: UnitSystem, compact: bool) -> &'static str {
+        match (self, system, compact) {
+            (Unit::B, _, _) => "B",
+            (Unit::K, _, true) => "K",
+            (Unit::M, _, true) => "M",
+            (Unit::G, _, true) => "G",
+            (Unit::T, _, true) => "T",
+            (Unit::K, UnitSystem::Si, false) => "KB",
+            (Unit::M, UnitSystem::Si, false) => "MB",
+            (Unit::G, UnitSystem::Si, false) => "GB",
+            (Unit::T, UnitSystem::Si, false) => "TB",
+            (Unit::K, UnitSystem::Iec, false) => "KiB",
+            (Unit::M, UnitSystem::Iec, false) => "MiB",
+            (Unit::G, UnitSystem::Iec, false) => "GiB",
+            (Unit::T, UnitSystem::Iec, false) => "TiB",
+        }
+    }
+}
+
+/// The time zone start times are shown in
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum Timezone {
+    #[default]
+    Local,
+    Utc,
+    /// A fixed offset from UTC, written as `+02:00` or `-0530`
+    Offset(FixedOffset),
+}
+
+impl Timezone {
+    /// The wall clock time of `datetime` in this time zone
+    pub fn naive(self, datetime: DateTime<Utc>) -> NaiveDateTime {
+        match self {
+            Timezone::Local => datetime.with_timezone(&Local).naive_local(),
+            Timezone::Utc => datetime.naive_utc(),
+            Timezone::Offset(offset) => datetime.with_timezone(&offset).naive_local(),
+        }
+    }
+
+    /// The moment a wall clock time in this time zone stands for, or `None` if
+    /// the clock skips it; of a time the clock passes twice, the earlier one
+    pub fn resolve(self, naive: NaiveDateTime) -> Option<DateTime<Utc>> {
+        match self {
+            Timezone::Local => Local.from_local_datetime(&naive).earliest().map(|datetime| datetime.to_utc()),
+            Timezone::Utc => Some(naive.and_utc()),
+            Timezone::Offset(offset) => offset.from_local_datetime(&naive).single().map(|datetime| datetime.to_utc()),
+        }
+    }
+}
+
+impl FromStr for Timezone {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        match text.trim().to_lowercase().as_str() {
+            "local" => Ok(Timezone::Local),
+            "utc" | "z" => Ok(Timezone::Utc),
+            offset => {
+                // Parse the offset on its own by giving it the rest of a timestamp
+                DateTime::parse_from_str(&format!("2000-01-01 00:00 {}", offset), "%Y-%m-%d %H:%M %z")
+                    .map(|datetime| Timezone::Offset(*datetime.offset()))
+                    .map_err(|_| {
+                        format!("invalid time zone '{}', expected local, utc or an offset like +02:00", text)
+                    })
+            }
+        }
+    }
+}
+
+impl fmt::Display for Timezone {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Timezone::Local => write!(f, "local"),
+            Timezone::Utc => write!(f, "utc"),
+            Timezone::Offset(offset) => write!(f, "{}", offset),
+        }
+    }
+}
+
+impl TryFrom<String> for Timezone {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        text.parse()
+    }
+}
+
+impl From<Timezone> for String {
+    fn from(timezone: Timezone) -> Self {
+        timezone.to_string()
+    }
+}
+
+/// How values are rendered in the table, the summary and the exports
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FormatOptions {
+    /// Pick a unit per value instead of showing whole MB
+    pub human_readable: bool,
+    pub units: UnitSystem,
+    /// Show all memory in this unit; overrides `human_readable`
+    pub unit: Option<Unit>,
+    /// Decimal places for memory, or `None` for the default of each mode
+    pub precision: Option<usize>,
+    /// Short forms like `1.2G` instead of `1.20 GB`
+    pub compact: bool,
+    /// Time zone for start times
+    pub timezone: Timezone,
+}
+
+impl FormatOptions {
+    /// The unit every memory value is shown in, or `None` to pick one per value
+    pub fn fixed_unit(&self) -> Option<Unit> {
+        match self.unit {
+            Some(unit) => Some(unit),
+            None if !self.human_readable => Some(Unit::M),
+            None => None,
+        }
+    }
+
+    /// The largest unit that keeps `bytes` at or above one, unless a unit is fixed
+    fn unit_for(&self, bytes: u64) -> Unit {
+        self.fixed_unit().unwrap_or_else(|| {
+            Unit::ALL
+                .into_iter()
+                .rev()
+                .find(|unit| bytes as f64 >= unit.factor(self.units))
+                .unwrap_or(Unit::B)
+        })
+    }
+
+    /// Decimal places for a value shown in `unit`
+    fn precision_for(&self, unit: Unit) -> usize {
+        match self.precision {
+            Some(precision) => precision,
+            None if unit == Unit::B => 0,
+            // Whole numbers keep the traditional MB column compact
+            None if self.fixed_unit().is_some() => if unit >= Unit::G { 2 } else { 0 },
+            None if self.compact => 1,
+            None => 2,
+        }
+    }
+
+    /// `bytes` in the unit it is displayed in, rounded to the displayed precision
+    pub fn scaled(&self, bytes: u64) -> (f64, Unit) {
+        let unit = self.unit_for(bytes);
+        let scale = 10f64.powi(self.precision_for(unit) as i32);
+        let value = bytes as f64 / unit.factor(self.units);
+        ((value * scale).round() / scale, unit)
+    }
+}
+
+/// Format bytes for a table cell.
+///
+/// With a fixed unit only the number is shown, since the column header
+/// carries the unit; otherwise the unit is picked per value and appended.
+pub fn format_memory(bytes: u64, options: &FormatOptions) -> String {
+    if options.fixed_unit().is_some() {
+        let (value, unit) = options.scaled(bytes);
+        format!("{:.*}", options.precision_for(unit), value)
+    } else {
+        format_size(bytes, options)
+    }
+}
+
+/// Format bytes with their unit, e.g. `1.20 GB`, `1.12 GiB` or `1.1G`
+pub fn format_size(bytes: u64, options: &FormatOptions) -> String {
+    let (value, unit) = options.scaled(bytes);
+    let separator = if options.compact { "" } else { " " };
+    format!(
+        "{:.*}{}{}",
+        options.precision_for(unit),
+        value,
+        separator,
+        unit.label(options.units, options.compact)
+    )
+}
+
+/// Format a start time as of `now`, both in seconds since the epoch: `HH:MM:SS`
+/// for the 24 hours before it, with the date for older ones
+pub fn format_time(timestamp: u64, now: u64, options: &FormatOptions) -> String {
+    let datetime = DateTime::from_timestamp(timestamp as i64, 0)
+        .unwrap_or_else(|| DateTime::from_timestamp(0, 0).unwrap());
+    let pattern = if now.saturating_sub(timestamp) >= TimeDelta::days(1).num_seconds() as u64 {
+        "%b %d %H:%M"
+    } else {
+        "%H:%M:%S"
+    };
+
+    options.timezone.naive(datetime).format(pattern).to_string()
+}
+
+/// Format a number of seconds as elapsed time: `HH:MM:SS`, or `3d 04:12:09` past a day
+pub fn format_duration(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let hours = (seconds % 86_400) / 3_600;
+    let minutes = (seconds % 3_600) / 60;
+    let seconds = seconds % 60;
+
+    if days > 0 {
+        format!("{}d {:02}:{:02}:{:02}", days, hours, minutes, seconds)
+    } else {
+        format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+    }
+}
+
+/// Format CPU seconds like the TIME+ column of `top`: `minutes:seconds.hundredths`
+pub fn format_cpu_time(seconds: f64) -> String {
+    let hundredths = (seconds.max(0.0) * 100.0).round() as u64;
+    format!(
+        "{}:{:02}.{:02}",
+        hundredths / 6_000,
+        (hundredths / 100) % 60,
+        hundredths % 100
+    )
+}
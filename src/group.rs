@@ -0,0 +1,157 @@
+//! Combining processes into one row per group, such as per name or per container.
+
+use crate::columns::Column;
+use crate::snapshot::UsageInfo;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fmt;
+
+/// Name of the group of processes that don't run in a container
+const HOST: &str = "host";
+
+/// What processes are grouped by
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum GroupBy {
+    /// The process name, e.g. all chrome processes together
+    Name,
+    /// The owner
+    User,
+    /// The parent, so each row is the children of one process
+    Ppid,
+    /// The control group
+    Cgroup,
+    /// The container a process runs in; the others are grouped as "host"
+    Container,
+}
+
+impl GroupBy {
+    /// Every grouping, in the order the interactive key cycles through them
+    pub const ALL: &'static [GroupBy] = &[
+        GroupBy::Name,
+        GroupBy::User,
+        GroupBy::Ppid,
+        GroupBy::Cgroup,
+        GroupBy::Container,
+    ];
+
+    /// Whether a group row has a value in `column` that its members share or
+    /// add up to, rather than one of the member the row was built from
+    pub fn describes(self, column: Column) -> bool {
+        match column {
+            Column::Pid
+            | Column::Tid
+            | Column::Tgid
+            | Column::State
+            | Column::Nice
+            | Column::Priority
+            | Column::Cmdline
+            | Column::Exe => false,
+            Column::Ppid => self == GroupBy::Ppid,
+            Column::User => self == GroupBy::User,
+            Column::Cgroup => self == GroupBy::Cgroup,
+            // Every process of a cgroup runs in the same container
+            Column::Container => matches!(self, GroupBy::Container | GroupBy::Cgroup),
+            _ => true,
+        }
+    }
+
+    /// What tells the groups apart
+    fn key(self, stat: &UsageInfo) -> String {
+        match self {
+            GroupBy::Name => stat.name.clone(),
+            GroupBy::User => stat.user.clone(),
+            GroupBy::Ppid => stat.ppid.to_string(),
+            GroupBy::Cgroup => stat.cgroup.clone(),
+            GroupBy::Container => stat
+                .container
+                .as_ref()
+                .map_or_else(|| HOST.to_string(), |container| container.short_id().to_string()),
+        }
+    }
+
+    /// Name of the group shown in the COMMAND column; `names` maps PIDs to
+    /// process names so a group of children can be named after its parent
+    fn label(self, key: String, names: &HashMap<u32, String>) -> String {
+        match self {
+            GroupBy::Ppid => match key.parse().ok().and_then(|ppid| names.get(&ppid)) {
+                Some(parent) => format!("{} ({})", parent, key),
+                None => key,
+            },
+            _ => key,
+        }
+    }
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            GroupBy::Name => "name",
+            GroupBy::User => "user",
+            GroupBy::Ppid => "ppid",
+            GroupBy::Cgroup => "cgroup",
+            GroupBy::Container => "container",
+        };
+        f.write_str(name)
+    }
+}
+
+/// Replace `stats` with one row per group, named after the group.
+///
+/// The row counts the members in `count`, sums their CPU usage, every memory figure,
+/// threads and I/O counters, and starts when the oldest member started. The
+/// other fields come from the member with the lowest PID, which is usually
+/// the one that started the rest, such as a container's init.
+pub fn group(stats: Vec<UsageInfo>, by: GroupBy) -> Vec<UsageInfo> {
+    let names: HashMap<u32, String> = match by {
+        GroupBy::Ppid => stats.iter().map(|stat| (stat.pid, stat.name.clone())).collect(),
+        _ => HashMap::new(),
+    };
+
+    let mut groups: HashMap<String, Vec<UsageInfo>> = HashMap::new();
+    for stat in stats {
+        groups.entry(by.key(&stat)).or_default().push(stat);
+    }
+
+    groups
+        .into_iter()
+        .map(|(key, members)| combine(by.label(key, &names), &members))
+        .collect()
+}
+
+/// Sum `members` into a single row named `label`
+fn combine(label: String, members: &[UsageInfo]) -> UsageInfo {
+    let representative = members
+        .iter()
+        .min_by_key(|member| member.pid)
+        .cloned()
+        .unwrap_or_default();
+
+    UsageInfo {
+        name: label,
+        count: members.len() as u32,
+        threads: members.iter().map(|member| member.threads).sum(),
+        cpu: members.iter().map(|member| member.cpu).sum(),
+        cpu_time: members.iter().map(|member| member.cpu_time).sum(),
+        mem: members.iter().map(|member| member.mem).sum(),
+        virt: members.iter().map(|member| member.virt).sum(),
+        shared: members.iter().map(|member| member.shared).sum(),
+        pss: members.iter().map(|member| member.pss).sum(),
+        uss: members.iter().map(|member| member.uss).sum(),
+        mapped_shared: members.iter().map(|member| member.mapped_shared).sum(),
+        swap: members.iter().map(|member| member.swap).sum(),
+        start_time: members
+            .iter()
+            .map(|member| member.start_time)
+            .min()
+            .unwrap_or_default(),
+        read_bytes: members.iter().map(|member| member.read_bytes).sum(),
+        write_bytes: members.iter().map(|member| member.write_bytes).sum(),
+        read_syscalls: members.iter().map(|member| member.read_syscalls).sum(),
+        write_syscalls: members.iter().map(|member| member.write_syscalls).sum(),
+        read_rate: members.iter().map(|member| member.read_rate).sum(),
+        write_rate: members.iter().map(|member| member.write_rate).sum(),
+        ..representative
+    }
+}
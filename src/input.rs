@@ -0,0 +1,584 @@
+//! Keyboard handling for the interactive UI.
+
+use crate::signals::{self, SIGNALS};
+use crate::filter::KernelView;
+use crate::replay::{Playback, SEEK_FORMATS};
+use rustop::collector::CpuMode;
+use rustop::expr::Expr;
+use rustop::group::GroupBy;
+use crate::{Args, MIN_REFRESH_RATE, SortBy, format_options, table_columns};
+use rustop::sort::SortKey;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use std::collections::HashSet;
+use std::sync::mpsc::Sender;
+use std::thread;
+
+/// Step used by `+` and `-` to change the refresh rate, in seconds
+const REFRESH_STEP: f64 = 0.5;
+
+/// Finer step used by `+` and `-` below one second
+const FINE_REFRESH_STEP: f64 = 0.1;
+
+/// Slowest refresh rate reachable with `+`, in seconds
+const MAX_REFRESH_RATE: f64 = 60.0;
+
+/// Rows skipped by Page Up and Page Down
+const PAGE_ROWS: usize = 10;
+
+/// Keys listed by `?`, with what they do
+const KEYS: &[(&str, &str)] = &[
+    ("c m p t i", "Sort by CPU, memory, PID, start time or disk I/O"),
+    ("< >", "Sort by the column left or right of the current one"),
+    ("r", "Reverse the sort direction"),
+    ("/", "Edit the name filter"),
+    ("e", "Edit the filter expression"),
+    ("Up Down", "Move the selection, also PgUp, PgDn, Home and End"),
+    ("h", "Show or hide the system summary"),
+    ("I", "Switch between Irix and Solaris mode"),
+    ("K", "Cycle between all processes, userland only and kernel only"),
+    ("Enter", "Open the detail pane of the selected process"),
+    ("k", "Send a signal to the selected process"),
+    ("H", "Show the threads of the selected process, or of all"),
+    ("T", "Toggle the tree view"),
+    ("G", "Cycle through the groupings"),
+    ("Space", "Collapse or expand the selected subtree"),
+    ("R", "Toggle CPU and memory rolled up over subtrees"),
+    ("+ -", "Change the refresh rate"),
+    ("?", "Show this list"),
+    ("q Esc", "Quit"),
+];
+
+/// Keys listed by `?` in a replay, before the others
+const PLAYBACK_KEYS: &[(&str, &str)] = &[
+    ("P", "Pause or resume"),
+    ("Left Right", "Step one snapshot back or forward"),
+    ("g", "Seek to a time"),
+];
+
+/// Whether keys are interpreted as commands or typed into a prompt
+pub enum InputMode {
+    Normal,
+    /// Editing the name filter; `previous` is restored when the edit is cancelled
+    Filter { previous: Option<String> },
+    /// Editing the filter expression; `previous` is restored when the edit is cancelled
+    Expr { previous: Option<String> },
+    /// Choosing a signal from `SIGNALS` to send to `pid`
+    SignalMenu { pid: u32, index: usize },
+    /// Waiting for confirmation before sending `SIGNALS[index]` to `pid`
+    ConfirmSignal { pid: u32, index: usize },
+    /// Showing the detail pane of `pid`, scrolled down by `scroll` lines
+    Details { pid: u32, scroll: usize },
+    /// Showing the keys, scrolled down by `scroll` lines
+    Help { scroll: usize },
+    /// Typing the time to jump to in a replay
+    Seek { text: String },
+}
+
+/// Interactive state that is not part of the command line settings
+pub struct UiState {
+    pub mode: InputMode,
+    /// PID of the highlighted row; it stays on the process when rows move
+    pub selected: Option<u32>,
+    /// PIDs whose children are hidden in the tree view
+    pub collapsed: HashSet<u32>,
+    /// Result of the last action, shown until the next key press
+    pub message: Option<String>,
+    /// Position in the recording when replaying one, `None` when showing live processes
+    pub playback: Option<Playback>,
+}
+
+impl UiState {
+    pub fn new() -> Self {
+        Self {
+            mode: InputMode::Normal,
+            selected: None,
+            collapsed: HashSet::new(),
+            message: None,
+            playback: None,
+        }
+    }
+
+    /// Move the selection by `delta` rows within `visible`, the PIDs in display order
+    fn move_selection(&mut self, visible: &[u32], delta: isize) {
+        if visible.is_empty() {
+            return;
+        }
+
+        let target = match self.selected.and_then(|pid| visible.iter().position(|&p| p == pid)) {
+            Some(index) => index.saturating_add_signed(delta).min(visible.len() - 1),
+            None => 0,
+        };
+        self.selected = Some(visible[target]);
+    }
+}
+
+/// What the main loop should do after a key press
+#[derive(Debug, PartialEq, Eq)]
+pub enum KeyOutcome {
+    /// Nothing visible changed
+    None,
+    /// Settings changed, redraw the last sample with them
+    Redraw,
+    /// What the rows are changed, take a new sample right away
+    Resample,
+    /// Leave rustop
+    Quit,
+}
+
+/// Read key events on a background thread so the UI stays responsive between refreshes
+pub fn spawn_reader(tx: Sender<KeyEvent>) {
+    thread::spawn(move || {
+        while let Ok(event) = event::read() {
+            if let Event::Key(key) = event
+                && key.kind == KeyEventKind::Press
+                && tx.send(key).is_err()
+            {
+                break;
+            }
+        }
+    });
+}
+
+/// Apply a key press to the current settings; `visible` holds the PIDs of the displayed rows
+pub fn handle_key(key: KeyEvent, ui: &mut UiState, args: &mut Args, visible: &[u32]) -> KeyOutcome {
+    // Raw mode swallows SIGINT, so Ctrl-C has to be handled as a key
+    if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+        return KeyOutcome::Quit;
+    }
+
+    ui.message = None;
+
+    let mode = &mut ui.mode;
+    match mode {
+        InputMode::Normal => handle_normal_key(key, ui, args, visible),
+        InputMode::SignalMenu { pid, index } => {
+            match key.code {
+                KeyCode::Esc | KeyCode::Char('q') => *mode = InputMode::Normal,
+                KeyCode::Left | KeyCode::Up => *index = index.saturating_sub(1),
+                KeyCode::Right | KeyCode::Down => *index = (*index + 1).min(SIGNALS.len() - 1),
+                KeyCode::Enter => {
+                    *mode = InputMode::ConfirmSignal {
+                        pid: *pid,
+                        index: *index,
+                    }
+                }
+                KeyCode::Char(c) => match c.to_digit(10) {
+                    Some(n) if (1..=SIGNALS.len() as u32).contains(&n) => {
+                        *mode = InputMode::ConfirmSignal {
+                            pid: *pid,
+                            index: n as usize - 1,
+                        }
+                    }
+                    _ => return KeyOutcome::None,
+                },
+                _ => return KeyOutcome::None,
+            }
+            KeyOutcome::Redraw
+        }
+        InputMode::Details { scroll, .. } | InputMode::Help { scroll } => {
+            match key.code {
+                KeyCode::Esc | KeyCode::Enter | KeyCode::Char('q' | '?') => *mode = InputMode::Normal,
+                KeyCode::Up => *scroll = scroll.saturating_sub(1),
+                KeyCode::Down => *scroll = scroll.saturating_add(1),
+                KeyCode::PageUp => *scroll = scroll.saturating_sub(PAGE_ROWS),
+                KeyCode::PageDown => *scroll = scroll.saturating_add(PAGE_ROWS),
+                KeyCode::Home => *scroll = 0,
+                // Clamped to the last page when drawn
+                KeyCode::End => *scroll = usize::MAX,
+                _ => return KeyOutcome::None,
+            }
+            KeyOutcome::Redraw
+        }
+        InputMode::Seek { text } => {
+            match key.code {
+                // An invalid time keeps the prompt open, the status line says why
+                KeyCode::Enter => {
+                    let timezone = format_options(args).timezone;
+                    if let Some(playback) = ui.playback.as_mut()
+                        && playback.seek(text, timezone).is_ok()
+                    {
+                        *mode = InputMode::Normal;
+                    }
+                }
+                KeyCode::Esc => *mode = InputMode::Normal,
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Char(c) => text.push(c),
+                _ => return KeyOutcome::None,
+            }
+            KeyOutcome::Redraw
+        }
+        InputMode::ConfirmSignal { pid, index } => {
+            let (pid, (signal_name, signal)) = (*pid, SIGNALS[*index]);
+            if matches!(key.code, KeyCode::Char('y') | KeyCode::Char('Y')) {
+                ui.message = Some(match signals::send(pid, signal) {
+                    Ok(()) => format!("Sent {} to {}", signal_name, pid),
+                    Err(e) => format!("Failed to send {} to {}: {}", signal_name, pid, e),
+                });
+            }
+            ui.mode = InputMode::Normal;
+            KeyOutcome::Redraw
+        }
+        InputMode::Filter { previous } => {
+            match key.code {
+                KeyCode::Enter => *mode = InputMode::Normal,
+                KeyCode::Esc => {
+                    args.filter = previous.take();
+                    *mode = InputMode::Normal;
+                }
+                KeyCode::Backspace => {
+                    if let Some(filter) = args.filter.as_mut() {
+                        filter.pop();
+                    }
+                }
+                KeyCode::Char(c) => args.filter.get_or_insert_with(String::new).push(c),
+                _ => return KeyOutcome::None,
+            }
+
+            // An empty filter matches everything, so drop it instead of keeping ""
+            if args.filter.as_deref() == Some("") && matches!(mode, InputMode::Normal) {
+                args.filter = None;
+            }
+            KeyOutcome::Redraw
+        }
+        InputMode::Expr { previous } => {
+            match key.code {
+                // An invalid expression keeps the prompt open, the status line says why
+                KeyCode::Enter => {
+                    if expr_error(args).is_none() {
+                        *mode = InputMode::Normal;
+                    }
+                }
+                KeyCode::Esc => {
+                    args.expr = previous.take();
+                    *mode = InputMode::Normal;
+                }
+                KeyCode::Backspace => {
+                    if let Some(expr) = args.expr.as_mut() {
+                        expr.pop();
+                    }
+                }
+                KeyCode::Char(c) => args.expr.get_or_insert_with(String::new).push(c),
+                _ => return KeyOutcome::None,
+            }
+
+            if args.expr.as_deref() == Some("") && matches!(mode, InputMode::Normal) {
+                args.expr = None;
+            }
+            KeyOutcome::Redraw
+        }
+    }
+}
+
+/// Why the expression in `args` doesn't parse, if it is set and doesn't
+fn expr_error(args: &Args) -> Option<String> {
+    args.expr
+        .as_deref()
+        .filter(|expr| !expr.trim().is_empty())
+        .and_then(|expr| Expr::parse(expr).err())
+        .map(|e| e.to_string())
+}
+
+/// Apply a key that controls the playback of a replay, if it is one
+fn handle_playback_key(key: KeyEvent, ui: &mut UiState) -> Option<KeyOutcome> {
+    let playback = ui.playback.as_mut()?;
+    match key.code {
+        KeyCode::Char('P') => playback.toggle_pause(),
+        KeyCode::Left => playback.step(-1),
+        KeyCode::Right => playback.step(1),
+        KeyCode::Char('g') => {
+            ui.mode = InputMode::Seek {
+                text: String::new(),
+            }
+        }
+        // The recorded processes are long gone or reused
+        KeyCode::Char('k') => ui.message = Some("Signals can't be sent from a replay".to_string()),
+        KeyCode::Char('H') => ui.message = Some("Threads are not recorded".to_string()),
+        _ => return None,
+    }
+    Some(KeyOutcome::Redraw)
+}
+
+fn handle_normal_key(key: KeyEvent, ui: &mut UiState, args: &mut Args, visible: &[u32]) -> KeyOutcome {
+    if let Some(outcome) = handle_playback_key(key, ui) {
+        return outcome;
+    }
+
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => return KeyOutcome::Quit,
+        KeyCode::Char('c') => set_preset(args, SortBy::Cpu),
+        KeyCode::Char('m') => set_preset(args, SortBy::Memory),
+        KeyCode::Char('p') => set_preset(args, SortBy::Pid),
+        KeyCode::Char('t') => set_preset(args, SortBy::StartTime),
+        KeyCode::Char('i') => set_preset(args, SortBy::Io),
+        KeyCode::Char('r') => {
+            let mut keys = args.sort_keys();
+            if let Some(primary) = keys.first_mut() {
+                primary.descending = !primary.descending;
+            }
+            args.sort = Some(keys);
+        }
+        KeyCode::Char('<') => shift_sort_column(args, -1),
+        KeyCode::Char('>') => shift_sort_column(args, 1),
+        KeyCode::Char('/') => {
+            ui.mode = InputMode::Filter {
+                previous: args.filter.clone(),
+            };
+        }
+        KeyCode::Char('e') => {
+            ui.mode = InputMode::Expr {
+                previous: args.expr.clone(),
+            };
+        }
+        KeyCode::Char('+') => {
+            let step = if args.refresh_rate < 1.0 { FINE_REFRESH_STEP } else { REFRESH_STEP };
+            args.refresh_rate = round_rate(args.refresh_rate + step).min(MAX_REFRESH_RATE);
+        }
+        KeyCode::Char('-') => {
+            let step = if args.refresh_rate <= 1.0 { FINE_REFRESH_STEP } else { REFRESH_STEP };
+            args.refresh_rate = round_rate(args.refresh_rate - step).max(MIN_REFRESH_RATE);
+        }
+        // A group row stands for many processes, none of which it could pick
+        KeyCode::Enter | KeyCode::Char('k') if args.group_by.is_some() => {
+            ui.message = Some("Groups have no single process, press G until ungrouped".to_string());
+        }
+        KeyCode::Enter => match ui.selected {
+            Some(pid) => ui.mode = InputMode::Details { pid, scroll: 0 },
+            None => ui.message = Some("Select a process with Up/Down first".to_string()),
+        },
+        KeyCode::Char('k') => match ui.selected {
+            Some(pid) => ui.mode = InputMode::SignalMenu { pid, index: 0 },
+            None => ui.message = Some("Select a process with Up/Down first".to_string()),
+        },
+        KeyCode::Char('?') => ui.mode = InputMode::Help { scroll: 0 },
+        KeyCode::Char('h') => args.no_summary = !args.no_summary,
+        KeyCode::Char('I') => args.cpu_mode = args.cpu_mode.toggled(),
+        KeyCode::Char('K') => {
+            // Cycle through all processes, userland only and kernel only
+            (args.no_kernel, args.kernel_only) = match args.kernel_view() {
+                KernelView::All => (true, false),
+                KernelView::UserlandOnly => (false, true),
+                KernelView::KernelOnly => (false, false),
+            };
+        }
+        KeyCode::Char('H') => {
+            if args.thread_view() {
+                // Back to processes, with the one whose threads were shown selected
+                ui.selected = args.threads.take();
+                args.all_threads = false;
+            } else {
+                // Threads of the selected process, or of all processes if none is
+                args.threads = ui.selected.take();
+                args.all_threads = args.threads.is_none();
+            }
+            return KeyOutcome::Resample;
+        }
+        KeyCode::Char('T') => args.tree = !args.tree,
+        KeyCode::Char('G') => {
+            // Cycle through each grouping and back to single processes
+            args.group_by = match args.group_by {
+                None => GroupBy::ALL.first().copied(),
+                Some(by) => GroupBy::ALL
+                    .iter()
+                    .skip_while(|&&other| other != by)
+                    .nth(1)
+                    .copied(),
+            };
+        }
+        KeyCode::Char('R') => args.tree_rollup = !args.tree_rollup,
+        KeyCode::Char(' ') => {
+            if let Some(pid) = ui.selected
+                && !ui.collapsed.remove(&pid)
+            {
+                ui.collapsed.insert(pid);
+            }
+        }
+        KeyCode::Up => ui.move_selection(visible, -1),
+        KeyCode::Down => ui.move_selection(visible, 1),
+        KeyCode::PageUp => ui.move_selection(visible, -(PAGE_ROWS as isize)),
+        KeyCode::PageDown => ui.move_selection(visible, PAGE_ROWS as isize),
+        KeyCode::Home => ui.move_selection(visible, isize::MIN),
+        KeyCode::End => ui.move_selection(visible, isize::MAX),
+        _ => return KeyOutcome::None,
+    }
+
+    KeyOutcome::Redraw
+}
+
+/// Round a refresh rate to tenths of a second so repeated steps don't drift
+fn round_rate(rate: f64) -> f64 {
+    (rate * 10.0).round() / 10.0
+}
+
+/// Switch to a sort preset, dropping any explicit `--sort` keys
+fn set_preset(args: &mut Args, preset: SortBy) {
+    args.sort_by = preset;
+    args.sort = None;
+}
+
+/// Make the displayed column left or right of the current primary key the new
+/// primary key, keeping the remaining keys as tie-breakers
+fn shift_sort_column(args: &mut Args, step: isize) {
+    let columns: Vec<_> = table_columns(args).into_iter().map(|spec| spec.column).collect();
+    if columns.is_empty() {
+        return;
+    }
+
+    let mut keys = args.sort_keys();
+    let current = keys
+        .first()
+        .and_then(|key| columns.iter().position(|&column| column == key.column));
+    let index = match current {
+        Some(index) => (index as isize + step).rem_euclid(columns.len() as isize) as usize,
+        None => 0,
+    };
+
+    let primary = SortKey::new(columns[index]);
+    keys.retain(|key| key.column != primary.column);
+    keys.insert(0, primary);
+    args.sort = Some(keys);
+}
+
+/// Text for the status line above the process table
+pub fn status_line(ui: &UiState, args: &Args) -> String {
+    match &ui.mode {
+        InputMode::Filter { .. } => {
+            format!("Filter: {}_", args.filter.as_deref().unwrap_or(""))
+        }
+        InputMode::Expr { .. } => {
+            let mut status = format!("Expression: {}_", args.expr.as_deref().unwrap_or(""));
+            if let Some(error) = expr_error(args) {
+                status.push_str(&format!("  ({})", error));
+            }
+            status
+        }
+        InputMode::SignalMenu { pid, index } => {
+            let choices: Vec<String> = SIGNALS
+                .iter()
+                .enumerate()
+                .map(|(i, (name, _))| {
+                    let name = name.trim_start_matches("SIG");
+                    if i == *index {
+                        format!("[{} {}]", i + 1, name)
+                    } else {
+                        format!(" {} {} ", i + 1, name)
+                    }
+                })
+                .collect();
+            format!("Signal for {}: {}", pid, choices.join(""))
+        }
+        InputMode::ConfirmSignal { pid, index } => {
+            format!("Send {} to {}? [y/N]", SIGNALS[*index].0, pid)
+        }
+        InputMode::Details { pid, .. } => {
+            format!("Details of {}  [Up/Down/PgUp/PgDn] scroll  [Esc] back", pid)
+        }
+        InputMode::Help { .. } => "Keys  [Up/Down/PgUp/PgDn] scroll  [Esc] back".to_string(),
+        InputMode::Seek { text } => {
+            let mut status = format!("Seek to: {}_", text);
+            let timezone = format_options(args).timezone;
+            let error = ui
+                .playback
+                .as_ref()
+                .filter(|_| !text.trim().is_empty())
+                .and_then(|playback| playback.target(text, timezone).err());
+            match error {
+                Some(error) => status.push_str(&format!("  ({})", error)),
+                None if text.trim().is_empty() => status.push_str(&format!("  ({})", SEEK_FORMATS)),
+                None => {}
+            }
+            status
+        }
+        InputMode::Normal if ui.message.is_some() => ui.message.clone().unwrap_or_default(),
+        InputMode::Normal => {
+            let keys: Vec<String> = args.sort_keys().iter().map(SortKey::to_string).collect();
+            let mut status = match &ui.playback {
+                Some(playback) => format!("{}  ", playback.status(format_options(args).timezone)),
+                None => String::new(),
+            };
+            status.push_str(&format!(
+                "Sort: {}  Refresh: {:.1}s",
+                keys.join(","),
+                args.refresh_rate
+            ));
+            if let Some(filter) = &args.filter {
+                status.push_str(&format!("  Filter: {}", filter));
+            }
+            if let Some(expr) = &args.expr {
+                status.push_str(&format!("  Expr: {}", expr));
+            }
+            if let Some(container) = &args.container {
+                status.push_str(&format!("  Container: {}", container));
+            }
+            match args.kernel_view() {
+                KernelView::All => {}
+                KernelView::UserlandOnly => status.push_str("  Userland only"),
+                KernelView::KernelOnly => status.push_str("  Kernel only"),
+            }
+            if args.cpu_mode == CpuMode::Irix {
+                status.push_str("  Irix mode");
+            }
+            match args.threads {
+                Some(pid) => status.push_str(&format!("  Threads of {}", pid)),
+                None if args.all_threads => status.push_str("  All threads"),
+                None => {}
+            }
+            if let Some(by) = args.group_by {
+                status.push_str(&format!("  Grouped by {}", by));
+            }
+            if args.tree_view() {
+                status.push_str(if args.tree_rollup { "  Tree (rollup)" } else { "  Tree" });
+            }
+            status.push_str("  [?] keys");
+            status
+        }
+    }
+}
+
+/// Lines of the key list shown by `?`
+pub fn help_lines(ui: &UiState) -> Vec<String> {
+    let playback = match ui.playback {
+        Some(_) => PLAYBACK_KEYS,
+        None => &[],
+    };
+    playback
+        .iter()
+        .chain(KEYS)
+        .map(|(keys, action)| format!("  {:<12} {}", keys, action))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[test]
+    fn status_line_fits_80_columns() {
+        let ui = UiState::new();
+        for command_line in [
+            &["rustop"][..],
+            &["rustop", "--tree"],
+            &["rustop", "--group-by", "container", "--cpu-mode", "irix"],
+        ] {
+            let args = Args::parse_from(command_line);
+            let status = status_line(&ui, &args);
+            assert!(status.chars().count() <= 80, "{:?} is too long", status);
+        }
+    }
+
+    #[test]
+    fn question_mark_lists_the_keys() {
+        let mut ui = UiState::new();
+        let mut args = Args::parse_from(["rustop"]);
+        let key = KeyEvent::new(KeyCode::Char('?'), KeyModifiers::NONE);
+
+        handle_key(key, &mut ui, &mut args, &[]);
+        assert!(matches!(ui.mode, InputMode::Help { scroll: 0 }));
+        assert!(help_lines(&ui).iter().any(|line| line.contains("Send a signal")));
+
+        handle_key(key, &mut ui, &mut args, &[]);
+        assert!(matches!(ui.mode, InputMode::Normal));
+    }
+}
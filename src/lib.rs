@@ -0,0 +1,22 @@
+//! Process collection and filtering behind the rustop binary.
+//!
+//! The interactive UI lives in the binary; this library holds the parts that
+//! run on every refresh, from collection to the column registry and filter
+//! expressions, so they can be benchmarked on their own, along with the
+//! details read when a single process is inspected and the recordings that
+//! sessions are saved to and replayed from.
+
+pub mod cgroup;
+pub mod collector;
+pub mod columns;
+pub mod details;
+pub mod expr;
+pub mod filter;
+pub mod format;
+pub mod group;
+pub mod recording;
+pub mod size;
+pub mod snapshot;
+pub mod sort;
+pub mod source;
+pub mod users;
@@ -1,26 +1,53 @@
-use clap::{Parser, ValueEnum};
+use chrono::Utc;
+use clap::{Parser, Subcommand, ValueEnum};
 use crossterm::{
     cursor::{self, Hide, Show},
     execute,
-    style::{Color, Print, ResetColor, SetForegroundColor},
-    terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, size},
+    style::{Attribute, Color, Print, ResetColor, SetAttribute, SetForegroundColor},
+    terminal::{
+        Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode,
+        enable_raw_mode, size,
+    },
 };
-use libproc::libproc::pid_rusage::{RUsageInfoV2, pidrusage};
-use libproc::libproc::proc_pid::name;
-use libproc::processes;
-use std::{cmp::Ordering, io::Error, sync::atomic, thread, time::Duration};
-use std::{collections::HashMap, sync::Arc};
-use std::{io::ErrorKind, panic};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::Instant;
+use std::{io::Error, sync::atomic, thread, time::Duration};
+use std::collections::HashSet;
+use std::sync::Arc;
+use std::panic;
 use std::{
     io::{Stdout, Write, stdout},
     sync::atomic::AtomicBool,
 };
 use sysinfo::System;
 use serde::{Deserialize, Serialize};
-use chrono;
+use rustop::{collector, columns, expr, filter, format, group, recording, size, snapshot, sort, source, users};
 
 mod config;
+mod input;
+mod output;
+mod pane;
+mod replay;
+mod signals;
+mod summary;
+mod tree;
+use collector::{Collector, CpuMode};
+use columns::{Column, ColumnSpec};
 use config::Config;
+use expr::Expr;
+use format::{FormatOptions, Timezone, Unit, UnitSystem};
+use filter::{KernelView, MemoryMetric, ProcessFilter};
+use group::GroupBy;
+use input::{InputMode, KeyOutcome, UiState};
+use size::Size;
+use output::{OutputFormat, SnapshotWriter};
+use recording::Recorder;
+use snapshot::{Snapshot, SystemSummary, UsageInfo};
+use sort::SortKey;
+use source::ProcessSource;
+use tree::DisplayRow;
+use users::UserFilter;
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
 enum SortBy {
@@ -28,19 +55,43 @@ enum SortBy {
     Memory,
     Pid,
     StartTime,
+    Io,
+}
+
+impl SortBy {
+    /// The sort keys this preset stands for
+    fn keys(self) -> Vec<SortKey> {
+        let column = match self {
+            SortBy::Cpu => Column::Cpu,
+            SortBy::Memory => Column::Memory,
+            SortBy::Pid => Column::Pid,
+            SortBy::StartTime => Column::StartTime,
+            SortBy::Io => Column::Io,
+        };
+        vec![SortKey::new(column)]
+    }
 }
 
 #[derive(Parser, Debug)]
 #[command(author, version, about = "A simple top-like process viewer written in Rust", long_about = None)]
 struct Args {
-    /// Sort processes by CPU usage, memory usage, PID, or start time
+    /// Sort processes by CPU usage, memory usage, PID, start time or disk I/O rate
     #[arg(short, long, value_enum, default_value_t = SortBy::Cpu)]
     sort_by: SortBy,
 
+    /// Sort by any columns, comma-separated as name[:asc|desc] in order of precedence
+    /// (e.g. cpu:desc,mem:desc,pid:asc); overrides --sort-by
+    #[arg(long, value_delimiter = ',')]
+    sort: Option<Vec<SortKey>>,
+
     /// Refresh rate in seconds
     #[arg(short, long, default_value_t = 1.0)]
     refresh_rate: f64,
 
+    /// What 100% CPU stands for: one core (irix) or the whole machine (solaris)
+    #[arg(long, value_enum, default_value_t = CpuMode::Solaris)]
+    cpu_mode: CpuMode,
+
     /// Show only the top N processes
     #[arg(short, long)]
     top: Option<usize>,
@@ -49,18 +100,56 @@ struct Args {
     #[arg(short, long)]
     filter: Option<String>,
 
-    /// Show only processes owned by the specified user
+    /// Filter expression over the columns, combined with &&, || and !
+    /// (e.g. "name ~ /^cargo|rustc$/ && (cpu > 20 || mem > 1G) && user != root")
+    #[arg(short = 'e', long)]
+    expr: Option<String>,
+
+    /// Show only processes owned by these users: comma-separated names or UIDs,
+    /// prefix one with ! to hide it instead (e.g. alice,1001 or '!root')
     #[arg(short = 'u', long)]
     user: Option<String>,
 
-    /// Hide kernel processes
-    #[arg(short = 'k', long)]
+    /// Show only processes in the container whose ID starts with this
+    #[arg(long)]
+    container: Option<String>,
+
+    /// Hide kernel threads
+    #[arg(short = 'k', long, conflicts_with = "kernel_only")]
     no_kernel: bool,
 
-    /// Display memory in human-readable format (KB, MB, GB)
+    /// Show only kernel threads
+    #[arg(short = 'K', long)]
+    kernel_only: bool,
+
+    /// Display memory in human-readable format, picking a unit per value (KB, MB, GB)
     #[arg(short = 'H', long)]
     human_readable: bool,
 
+    /// Count memory in powers of 1000 (si: KB, MB, GB) or 1024 (iec: KiB, MiB, GiB)
+    #[arg(long, value_enum, default_value_t = UnitSystem::Si)]
+    units: UnitSystem,
+
+    /// Show all memory in this unit (b, k, m, g or t); overrides --human-readable
+    #[arg(long, value_enum)]
+    mem_unit: Option<Unit>,
+
+    /// Decimal places for memory values
+    #[arg(long)]
+    precision: Option<usize>,
+
+    /// Short memory values like 1.2G instead of 1.20 GB
+    #[arg(long)]
+    compact: bool,
+
+    /// Time zone for start times: local, utc or an offset like +02:00
+    #[arg(long, default_value_t = Timezone::Local)]
+    timezone: Timezone,
+
+    /// Show start times in UTC, same as --timezone utc
+    #[arg(long, conflicts_with = "timezone")]
+    utc: bool,
+
     /// Generate a config file with current settings
     #[arg(short = 'g', long)]
     generate_config: bool,
@@ -73,164 +162,590 @@ struct Args {
     #[arg(long)]
     cpu_below: Option<f64>,
     
-    /// Filter processes with memory usage above this threshold (MB or in bytes if not human-readable)
+    /// Filter processes with memory usage above this size: a number with a unit,
+    /// B, K/KiB, M/MiB, G/GiB, T/TiB (powers of 1024) or KB/MB/GB/TB (powers of 1000)
     #[arg(long)]
-    mem_above: Option<u64>,
+    mem_above: Option<Size>,
     
-    /// Filter processes with memory usage below this threshold (MB or in bytes if not human-readable)
+    /// Filter processes with memory usage below this size, with a unit as for --mem-above
     #[arg(long)]
-    mem_below: Option<u64>,
+    mem_below: Option<Size>,
+
+    /// Which memory --mem-above and --mem-below compare: rss, pss, uss, shared, swap or virt
+    #[arg(long, value_enum, default_value_t = MemoryMetric::Rss)]
+    mem_metric: MemoryMetric,
+
+    /// Take a single sample, print it and exit (non-interactive mode)
+    #[arg(long, conflicts_with = "iterations")]
+    once: bool,
+
+    /// Take N samples, printing each to stdout, then exit (non-interactive mode)
+    #[arg(short = 'n', long)]
+    iterations: Option<usize>,
+
+    /// List the threads of this process instead of processes
+    #[arg(long, value_name = "PID", conflicts_with = "all_threads")]
+    threads: Option<u32>,
+
+    /// List the threads of all processes instead of processes
+    #[arg(long)]
+    all_threads: bool,
+
+    /// Show processes as a tree of parents and children
+    #[arg(long)]
+    tree: bool,
+
+    /// In tree view, show CPU and memory summed over each process's subtree
+    #[arg(long)]
+    tree_rollup: bool,
+
+    /// Show one row per group with the process count, summed CPU and memory and
+    /// the oldest start time
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// Hide the system summary (uptime, load, CPU cores, memory) above the table
+    #[arg(long)]
+    no_summary: bool,
+
+    /// Columns to show, comma-separated as name[:width][:left|right]; available columns:
+    /// pid, tid, tgid, ppid, user, state, nice, priority, threads, count, command, cpu, mem,
+    /// virt, rss, shr, pss, uss, shared, swap, cputime, time+, start, elapsed, io, read, write, read_total,
+    /// write_total, syscr, syscw, container, cgroup, cmdline, exe
+    #[arg(short = 'c', long, value_delimiter = ',')]
+    columns: Option<Vec<ColumnSpec>>,
+
+    /// Output format; anything other than table runs non-interactively until interrupted
+    #[arg(short = 'o', long, value_enum, default_value_t = OutputFormat::Table)]
+    output: OutputFormat,
+
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-struct UsageInfo {
-    pid: u32,
-    name: String,
-    cpu: f64,
-    mem: u64,
-    start_time: u64,
+/// Something to do instead of showing live processes; the options above go before it
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Append a snapshot of every process and the system summary to a file on each
+    /// refresh until interrupted
+    Record {
+        /// Recording to append to, created if it doesn't exist
+        #[arg(long, value_name = "FILE")]
+        out: PathBuf,
+    },
+    /// Play back a recording in the interactive UI, one snapshot per refresh interval
+    Replay {
+        /// Recording made with `rustop record`
+        file: PathBuf,
+    },
 }
 
-fn sample() -> (HashMap<u32, UsageInfo>, f64) {
-    let uptime = System::uptime() as f64;
-    let processes_by_type = processes::pids_by_type(processes::ProcFilter::All);
-    let mut first_sample = HashMap::new();
-    let mut sys = System::new_all();
-    sys.refresh_all();
-
-    if let Ok(ref pids) = processes_by_type {
-        for pid in pids.iter() {
-            let proc_name = name(*pid as i32).unwrap_or_else(|_| "Unknown".to_string());
-            let mut start_time = 0;
-            
-            // Get process start time using sysinfo
-            if let Some(process) = sys.process(sysinfo::Pid::from_u32(*pid)) {
-                start_time = process.start_time();
-            }
+impl Args {
+    /// Keys to sort by: the explicit `--sort` list or else the `--sort-by` preset
+    fn sort_keys(&self) -> Vec<SortKey> {
+        self.sort.clone().unwrap_or_else(|| self.sort_by.keys())
+    }
 
-            if let Ok(usage) = pidrusage::<RUsageInfoV2>(*pid as i32) {
-                let cpu_time = (usage.ri_system_time + usage.ri_user_time) as f64 / 1_000_000.0;
-                first_sample.insert(
-                    *pid,
-                    UsageInfo {
-                        pid: *pid,
-                        name: proc_name,
-                        cpu: cpu_time,
-                        mem: usage.ri_resident_size,
-                        start_time,
-                    },
-                );
-            }
+    /// Which processes to show with respect to the user/kernel split
+    fn kernel_view(&self) -> KernelView {
+        if self.kernel_only {
+            KernelView::KernelOnly
+        } else if self.no_kernel {
+            KernelView::UserlandOnly
+        } else {
+            KernelView::All
         }
     }
 
-    (first_sample, uptime)
+    /// Whether the table lists threads instead of processes
+    fn thread_view(&self) -> bool {
+        self.threads.is_some() || self.all_threads
+    }
+
+    /// Whether processes are shown as a tree; threads and groups are always listed flat
+    fn tree_view(&self) -> bool {
+        self.tree && !self.thread_view() && self.group_by.is_none()
+    }
+
+    /// Number of samples to print in batch mode, or `None` for the interactive UI
+    fn batch_iterations(&self) -> Option<usize> {
+        if self.once { Some(1) } else { self.iterations }
+    }
 }
 
-fn stats(num_cpus: f64, sample: (HashMap<u32, UsageInfo>, f64)) -> Vec<UsageInfo> {
-    let uptime_t2 = System::uptime() as f64;
-    let elapsed_time = (uptime_t2 - sample.1).max(0.01);
-
-    let mut proc_stats: Vec<UsageInfo> = Vec::new();
-
-    for (pid, info) in sample.0.iter() {
-        if let Ok(usage) = pidrusage::<RUsageInfoV2>(*pid as i32) {
-            let new_cpu_time = (usage.ri_system_time + usage.ri_user_time) as f64 / 1_000_000.0;
-            let cpu_usage = ((new_cpu_time - info.cpu) / elapsed_time) * (100.0 / num_cpus);
-            proc_stats.push(UsageInfo {
-                pid: *pid,
-                name: info.name.clone(),
-                cpu: cpu_usage,
-                mem: usage.ri_resident_size,
-                start_time: info.start_time,
-            });
-        }
+/// Shortest refresh interval in seconds
+const MIN_REFRESH_RATE: f64 = 0.1;
+
+/// Tick deadlines on the monotonic clock.
+///
+/// Deadlines are spaced by the refresh interval from the previous deadline, not
+/// from when the previous tick finished, so collection and drawing don't stretch
+/// the period. Ticks that were missed entirely are skipped instead of bunched up.
+struct Ticks {
+    last: Instant,
+}
+
+impl Ticks {
+    fn new(start: Instant) -> Self {
+        Self { last: start }
+    }
+
+    /// When the next tick is due
+    fn next(&self, refresh_rate: f64) -> Instant {
+        self.last + Duration::from_secs_f64(refresh_rate)
     }
 
-    proc_stats
+    /// Record that the tick due now has been taken
+    fn advance(&mut self, refresh_rate: f64) {
+        let now = Instant::now();
+        let next = self.next(refresh_rate);
+        self.last = if now.duration_since(next) > Duration::from_secs_f64(refresh_rate) {
+            now
+        } else {
+            next
+        };
+    }
 }
 
-/// Format bytes into human-readable format (KB, MB, GB)
-fn format_memory(bytes: u64, human_readable: bool) -> String {
-    if !human_readable {
-        return format!("{}", bytes / 1_000_000); // Default: MB
+/// Line width assumed for the system summary and the table when printing without a terminal
+const PLAIN_WIDTH: usize = 80;
+
+/// Columns of the process table.
+///
+/// The thread view shows TID in place of PID, adds TGID when threads of all
+/// processes are mixed, and leaves out the thread count. Grouped rows add
+/// the COUNT of processes after COMMAND.
+fn table_columns(args: &Args) -> Vec<ColumnSpec> {
+    let mut columns = args
+        .columns
+        .clone()
+        .unwrap_or_else(|| Column::DEFAULT.iter().map(|&column| column.into()).collect());
+
+    if args.thread_view() {
+        columns.retain(|spec| spec.column != Column::Threads);
+        for spec in columns.iter_mut().filter(|spec| spec.column == Column::Pid) {
+            spec.column = Column::Tid;
+        }
+        if args.all_threads && !columns.iter().any(|spec| spec.column == Column::Tgid) {
+            let index = columns
+                .iter()
+                .position(|spec| spec.column == Column::Tid)
+                .map_or(0, |index| index + 1);
+            columns.insert(index, Column::Tgid.into());
+        }
     }
 
-    const KB: u64 = 1_000;
-    const MB: u64 = 1_000_000;
-    const GB: u64 = 1_000_000_000;
+    // Grouped rows show how many processes they stand for
+    if args.group_by.is_some() && !columns.iter().any(|spec| spec.column == Column::Count) {
+        let index = columns
+            .iter()
+            .position(|spec| spec.column == Column::Command)
+            .map_or(columns.len(), |index| index + 1);
+        columns.insert(index, Column::Count.into());
+    }
+    columns
+}
 
-    if bytes >= GB {
-        format!("{:.2} GB", bytes as f64 / GB as f64)
-    } else if bytes >= MB {
-        format!("{:.2} MB", bytes as f64 / MB as f64)
-    } else if bytes >= KB {
-        format!("{:.2} KB", bytes as f64 / KB as f64)
-    } else {
-        format!("{} B", bytes)
+/// Columns of the process table on lines of `width` characters; the tree view
+/// gives COMMAND what the other columns leave, to make room for the glyphs
+fn line_columns(args: &Args, width: usize) -> Vec<ColumnSpec> {
+    let mut columns = table_columns(args);
+    if args.tree_view() {
+        // Columns are separated by a space
+        let row_width = columns.iter().map(|spec| spec.width + 1).sum::<usize>().saturating_sub(1);
+        if let Some(spec) = columns.iter_mut().find(|spec| spec.column == Column::Command) {
+            spec.width += width.saturating_sub(row_width);
+        }
     }
+    columns
 }
 
-/// Format timestamp into a human-readable format (HH:MM:SS)
-fn format_time(timestamp: u64) -> String {
-    let datetime = chrono::DateTime::from_timestamp(timestamp as i64, 0)
-        .unwrap_or_else(|| chrono::DateTime::from_timestamp(0, 0).unwrap());
-    
-    datetime.format("%H:%M:%S").to_string()
+/// How values are formatted according to `args`
+fn format_options(args: &Args) -> FormatOptions {
+    FormatOptions {
+        human_readable: args.human_readable,
+        units: args.units,
+        unit: args.mem_unit,
+        precision: args.precision,
+        compact: args.compact,
+        timezone: if args.utc { Timezone::Utc } else { args.timezone },
+    }
 }
 
-fn print(stdout: &mut Stdout, stats: Vec<UsageInfo>, args: &Args) {
-    let (_, rows) = size().unwrap_or((0, 0));
-    let lines_to_print = match args.top {
-        Some(n) => n.min((rows as usize).saturating_sub(2)),
-        None => (rows as usize).saturating_sub(2), // Reserve 2 lines for header
+/// Format the column header line of the process table, `width` characters wide
+fn format_header(args: &Args, width: usize) -> String {
+    let options = format_options(args);
+    line_columns(args, width)
+        .iter()
+        .map(|spec| spec.fit(&spec.column.header(&options)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Format a single process as a row of the process table, `width` characters wide
+fn format_row(row: &DisplayRow, args: &Args, width: usize) -> String {
+    let options = format_options(args);
+    line_columns(args, width)
+        .iter()
+        .map(|spec| match spec.column {
+            Column::Command => spec.fit(&row.label()),
+            // A group row only carries these for the member it was built from
+            column if args.group_by.is_some_and(|by| !by.describes(column)) => spec.fit(""),
+            column => spec.fit(&column.display(&row.info, &options)),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Turn filtered and sorted processes into table rows, as a tree if requested
+fn display_rows(stats: Vec<UsageInfo>, args: &Args, collapsed: &HashSet<u32>) -> Vec<DisplayRow> {
+    let mut rows = if args.tree_view() {
+        tree::build(stats, collapsed, args.tree_rollup)
+    } else {
+        tree::flat(stats)
     };
 
-    let mem_header = if args.human_readable {
-        "MEMORY"
+    if let Some(top) = args.top {
+        rows.truncate(top);
+    }
+    rows
+}
+
+fn print(
+    stdout: &mut Stdout,
+    rows: &[DisplayRow],
+    summary: &SystemSummary,
+    args: &Args,
+    ui: &UiState,
+) {
+    let (width, height) = size().unwrap_or((0, 0));
+    let summary_lines = if args.no_summary {
+        Vec::new()
     } else {
-        "MEMORY (MB)"
+        summary::lines(summary, width as usize, &format_options(args), args.thread_view())
     };
+    // Reserve lines for the summary, status and header
+    let lines_to_print = (height as usize).saturating_sub(summary_lines.len() + 2);
+
+    // Scroll just far enough to keep the selected row on screen
+    let selected_index = ui
+        .selected
+        .and_then(|pid| rows.iter().position(|row| row.info.pid == pid));
+    let offset = selected_index.map_or(0, |index| (index + 1).saturating_sub(lines_to_print));
 
     execute!(
         stdout,
         cursor::MoveTo(0, 0),
         Clear(ClearType::All),
+        Print(summary_lines.iter().map(|line| format!("{}\r\n", line)).collect::<String>()),
+        Print(cut(&input::status_line(ui, args), width as usize)),
         SetForegroundColor(Color::Green),
-        Print("\r\n"),
-        Print(format!(
-            "{:<6} {:<20} {:>10} {:>12} {:>10}\n",
-            "PID", "COMMAND", "CPU (%)", mem_header, "START TIME"
-        )),
+        Print(format!("\r\n{}", cut(&format_header(args, width as usize), width as usize))),
         ResetColor
     )
     .unwrap();
 
-    for stat in stats.iter().take(lines_to_print) {
-        execute!(
-            stdout,
-            SetForegroundColor(Color::DarkYellow),
-            Print(format!(
-                "\r{:<6} {:<20} {:>10} {:>12} {:>10}\n",
-                stat.pid,
-                &stat.name.chars().take(20).collect::<String>(), // Trim long process names
-                format!("{:.2}%", stat.cpu),
-                format_memory(stat.mem, args.human_readable),
-                format_time(stat.start_time),
-            )),
-            ResetColor
-        )
-        .unwrap();
+    // Rows start with the line break so the last one doesn't scroll the screen
+    for row in rows.iter().skip(offset).take(lines_to_print) {
+        if ui.selected == Some(row.info.pid) {
+            execute!(
+                stdout,
+                SetAttribute(Attribute::Reverse),
+                Print(format!("\r\n{}", cut(&format_row(row, args, width as usize), width as usize))),
+                SetAttribute(Attribute::Reset)
+            )
+            .unwrap();
+        } else {
+            execute!(
+                stdout,
+                SetForegroundColor(Color::DarkYellow),
+                Print(format!("\r\n{}", cut(&format_row(row, args, width as usize), width as usize))),
+                ResetColor
+            )
+            .unwrap();
+        }
     }
 
     stdout.flush().unwrap(); // Force immediate terminal update
 }
 
+/// The first `width` characters of `text`, so a line never wraps and scrolls
+/// the screen
+fn cut(text: &str, width: usize) -> &str {
+    match text.char_indices().nth(width) {
+        Some((end, _)) => &text[..end],
+        None => text,
+    }
+}
+
+/// Draw the detail pane or the key list below the status line, scrolled down
+/// by `scroll` lines.
+///
+/// Returns the scroll position actually used, which stops where the last line
+/// reaches the bottom of the screen.
+fn print_details(stdout: &mut Stdout, status: &str, lines: &[String], scroll: usize) -> usize {
+    let (width, height) = size().unwrap_or((0, 0));
+    let lines = pane::wrap(lines, width as usize);
+    // Reserve a line for the status
+    let lines_to_print = (height as usize).saturating_sub(1);
+    let scroll = scroll.min(lines.len().saturating_sub(lines_to_print));
+
+    execute!(
+        stdout,
+        cursor::MoveTo(0, 0),
+        Clear(ClearType::All),
+        SetForegroundColor(Color::Green),
+        Print(cut(status, width as usize)),
+        ResetColor
+    )
+    .unwrap();
+
+    for line in lines.iter().skip(scroll).take(lines_to_print) {
+        execute!(stdout, Print(format!("\r\n{}", line))).unwrap();
+    }
+
+    stdout.flush().unwrap();
+    scroll
+}
+
+/// Print the process table as plain text without colors or cursor movement
+fn print_plain(
+    out: &mut impl Write,
+    rows: &[DisplayRow],
+    summary: &SystemSummary,
+    args: &Args,
+) -> std::io::Result<()> {
+    if !args.no_summary {
+        for line in summary::lines(summary, PLAIN_WIDTH, &format_options(args), args.thread_view()) {
+            writeln!(out, "{}", line)?;
+        }
+        writeln!(out)?;
+    }
+
+    writeln!(out, "{}", format_header(args, PLAIN_WIDTH))?;
+    for row in rows {
+        writeln!(out, "{}", format_row(row, args, PLAIN_WIDTH))?;
+    }
+
+    out.flush()
+}
+
+/// Whether anything shown, sorted or filtered on needs the memory breakdown
+fn needs_memory_breakdown(args: &Args) -> bool {
+    let memory_bounds = args.mem_above.is_some() || args.mem_below.is_some();
+    let expr = args.expr.as_deref().and_then(|expr| Expr::parse(expr).ok());
+
+    table_columns(args).iter().any(|spec| spec.column.is_memory_breakdown())
+        || args.sort_keys().iter().any(|key| key.column.is_memory_breakdown())
+        || memory_bounds && args.mem_metric.column().is_memory_breakdown()
+        || expr.is_some_and(|expr| {
+            Column::ALL
+                .iter()
+                .any(|&column| column.is_memory_breakdown() && expr.uses(column))
+        })
+}
+
+/// Read the rows the table lists: processes, or threads in the thread view.
+///
+/// The memory breakdown is only read while something needs it, since it costs
+/// more than everything else about a process together.
+fn sample(collector: &mut Collector, args: &Args) -> Vec<UsageInfo> {
+    collector.set_memory_breakdown(needs_memory_breakdown(args));
+
+    if args.thread_view() {
+        collector.collect_threads(args.threads)
+    } else {
+        collector.collect()
+    }
+}
+
+/// Filter, sort and draw the latest sample, or the detail pane if it is open,
+/// returning the displayed PIDs in order
+fn render(
+    stdout: &mut Stdout,
+    latest: &[UsageInfo],
+    summary: &SystemSummary,
+    args: &Args,
+    ui: &mut UiState,
+    source: &dyn ProcessSource,
+) -> Vec<u32> {
+    let mut stats = latest.to_vec();
+    filter_and_sort(&mut stats, args);
+
+    let rows = display_rows(stats, args, &ui.collapsed);
+    if let InputMode::Details { pid, .. } | InputMode::SignalMenu { pid, .. } = &mut ui.mode
+        && let Some(thread) = latest.iter().find(|row| row.pid == *pid)
+    {
+        // A thread opens the details of and is signalled through the process
+        // it belongs to, since its ID is not a PID on every platform
+        *pid = thread.tgid;
+    }
+
+    if let InputMode::Help { scroll } = ui.mode {
+        let lines = input::help_lines(ui);
+        let scroll = print_details(stdout, &input::status_line(ui, args), &lines, scroll);
+        ui.mode = InputMode::Help { scroll };
+    } else if let InputMode::Details { pid, scroll } = ui.mode {
+        // In a replay the sample is the recorded one, not the current time
+        let now = latest
+            .iter()
+            .map(|row| row.sampled_at)
+            .max()
+            .unwrap_or_else(|| Utc::now().timestamp() as u64);
+        let lines = pane::lines(source, pid, now, &format_options(args));
+        let scroll = print_details(stdout, &input::status_line(ui, args), &lines, scroll);
+        ui.mode = InputMode::Details { pid, scroll };
+    } else {
+        print(stdout, &rows, summary, args, ui);
+    }
+
+    rows.iter().map(|row| row.info.pid).collect()
+}
+
+/// Read a memory threshold written as the bare `number` the way it used to be
+/// read, warning that it is deprecated
+fn resolve_unitless(name: &str, number: f64, human_readable: bool) -> Size {
+    let (size, explicit) = unitless_size(number, human_readable);
+    eprintln!(
+        "Warning: {} {} has no unit and is read as {}; unitless memory thresholds are \
+         deprecated, write {} instead",
+        name, number, explicit, explicit
+    );
+    size
+}
+
+/// What a bare `number` used to mean, as MB or as bytes with human-readable
+/// output, and how to write that with its unit
+fn unitless_size(number: f64, human_readable: bool) -> (Size, String) {
+    let (multiplier, unit) = if human_readable { (1.0, "B") } else { (1_000_000.0, "MB") };
+    let size = Size::from_bytes((number * multiplier).round() as u64);
+    (size, format!("{}{}", number, unit))
+}
+
+/// Build the process filter described by `args`
+fn process_filter(args: &Args) -> ProcessFilter {
+    ProcessFilter {
+        name: args.filter.as_ref().map(|filter| filter.to_lowercase()),
+        users: args.user.as_deref().map(UserFilter::parse),
+        kernel: args.kernel_view(),
+        cpu_above: args.cpu_above,
+        cpu_below: args.cpu_below,
+        mem_above: args.mem_above.map(Size::bytes),
+        mem_below: args.mem_below.map(Size::bytes),
+        mem_metric: args.mem_metric,
+        container: args.container.as_ref().map(|id| id.to_lowercase()),
+        // Checked at startup and by the prompt, so an invalid one is still being typed
+        expr: args.expr.as_deref().and_then(|expr| Expr::parse(expr).ok()),
+    }
+}
+
+/// Apply the filters from `args` to `stats`, group what remains if asked to and sort it.
+///
+/// When grouping, CPU and memory thresholds apply to the totals of the groups
+/// and the other filters to the processes that make them up.
+fn filter_and_sort(stats: &mut Vec<UsageInfo>, args: &Args) {
+    let mut filter = process_filter(args);
+
+    if let Some(by) = args.group_by {
+        let thresholds = filter.take_thresholds();
+        filter.apply(stats);
+        *stats = group::group(std::mem::take(stats), by);
+        thresholds.apply(stats);
+    } else {
+        filter.apply(stats);
+    }
+
+    // Sort based on the specified criteria
+    sort::sort_processes(stats, &args.sort_keys());
+}
+
+/// Append a snapshot of every process to the recording at `path` on each tick
+/// until SIGINT or SIGTERM, which stop it between two snapshots
+fn record(path: &Path, collector: &mut Collector, sys: &mut System, args: &Args) -> Result<(), Error> {
+    let mut recorder = Recorder::open(path, args.cpu_mode)?;
+    collector.set_mode(recorder.header().cpu_mode, &mut []);
+
+    let term = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+
+    // Like printed samples, recorded ones measure CPU over a full interval
+    sample(collector, args);
+    let mut ticks = Ticks::new(Instant::now());
+    let mut recorded = 0;
+
+    while !term.load(atomic::Ordering::Relaxed) {
+        // Sleep in short steps so an interrupt doesn't wait for a long interval
+        let deadline = ticks.next(args.refresh_rate);
+        while !term.load(atomic::Ordering::Relaxed) && Instant::now() < deadline {
+            thread::sleep(deadline.saturating_duration_since(Instant::now()).min(Duration::from_millis(100)));
+        }
+        if term.load(atomic::Ordering::Relaxed) {
+            break;
+        }
+        ticks.advance(args.refresh_rate);
+
+        let processes = sample(collector, args);
+        sys.refresh_cpu();
+        sys.refresh_memory();
+        let summary = SystemSummary::collect(sys, &processes);
+        recorder.write(&Snapshot::new(summary, processes))?;
+        recorded += 1;
+    }
+
+    eprintln!("Recorded {} snapshots to {}", recorded, path.display());
+    Ok(())
+}
+
+/// Switch to the full-screen UI, making sure the terminal is restored on a
+/// panic or signal.
+///
+/// Returns the flag SIGINT and SIGTERM set and the thread that restores the
+/// terminal when it is set, to be handed to `stop_ui`.
+fn start_ui(stdout: &mut Stdout) -> Result<(Arc<AtomicBool>, thread::JoinHandle<()>), Error> {
+    // Set up panic hook to ensure terminal is restored on panic
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |panic_info| {
+        let _ = cleanup_terminal(&mut std::io::stdout());
+        default_hook(panic_info);
+    }));
+
+    let term = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
+    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+
+    // Set up a cleanup handler for signals
+    let cleanup_on_signal = std::thread::spawn({
+        let term = Arc::clone(&term);
+        move || {
+            while !term.load(atomic::Ordering::Relaxed) {
+                std::thread::sleep(Duration::from_millis(100));
+            }
+            let _ = cleanup_terminal(&mut std::io::stdout());
+        }
+    });
+
+    let _ = setup_terminal(stdout);
+    Ok((term, cleanup_on_signal))
+}
+
+/// Leave the full-screen UI started with `start_ui`
+fn stop_ui(stdout: &mut Stdout, term: &AtomicBool, cleanup_on_signal: thread::JoinHandle<()>) {
+    let _ = cleanup_terminal(stdout);
+
+    // Set the termination flag to true and wait for the cleanup thread to finish
+    term.store(true, atomic::Ordering::Relaxed);
+    let _ = cleanup_on_signal.join();
+}
+
 fn setup_terminal(stdout: &mut Stdout) -> Result<(), Box<dyn std::error::Error>> {
     execute!(stdout, EnterAlternateScreen, Hide)?;
+    enable_raw_mode()?;
     Ok(())
 }
 
 fn cleanup_terminal(stdout: &mut Stdout) -> Result<(), Box<dyn std::error::Error>> {
+    disable_raw_mode()?;
     execute!(stdout, Show, LeaveAlternateScreen)?;
     Ok(())
 }
@@ -246,73 +761,193 @@ fn main() -> Result<(), Error> {
     let mut args = Args::parse();
 
     // Merge config with command line args (command line takes precedence)
-    if let Some(sort_by) = config.sort_by {
-        if !std::env::args().any(|arg| arg == "-s" || arg == "--sort-by") {
-            args.sort_by = sort_by;
-        }
+    if let Some(sort_by) = config.sort_by
+        && !std::env::args().any(|arg| arg == "-s" || arg == "--sort-by")
+    {
+        args.sort_by = sort_by;
     }
 
-    if let Some(refresh_rate) = config.refresh_rate {
-        if !std::env::args().any(|arg| arg == "-r" || arg == "--refresh-rate") {
-            args.refresh_rate = refresh_rate;
-        }
+    // A preset given on the command line also wins over sort keys from the config
+    if let Some(sort) = config.sort
+        && !std::env::args().any(|arg| arg == "-s" || arg.starts_with("--sort"))
+    {
+        args.sort = Some(sort);
     }
 
-    if let Some(top) = config.top {
-        if !std::env::args().any(|arg| arg == "-t" || arg == "--top") {
-            args.top = Some(top);
-        }
+    if let Some(refresh_rate) = config.refresh_rate
+        && !std::env::args().any(|arg| arg == "-r" || arg == "--refresh-rate")
+    {
+        args.refresh_rate = refresh_rate;
     }
 
-    if let Some(filter) = config.filter {
-        if !std::env::args().any(|arg| arg == "-f" || arg == "--filter") {
-            args.filter = Some(filter);
-        }
+    if let Some(cpu_mode) = config.cpu_mode
+        && !std::env::args().any(|arg| arg.starts_with("--cpu-mode"))
+    {
+        args.cpu_mode = cpu_mode;
     }
 
-    if let Some(user) = config.user {
-        if !std::env::args().any(|arg| arg == "-u" || arg == "--user") {
-            args.user = Some(user);
-        }
+    if let Some(top) = config.top
+        && !std::env::args().any(|arg| arg == "-t" || arg == "--top")
+    {
+        args.top = Some(top);
     }
 
-    if let Some(no_kernel) = config.no_kernel {
-        if !std::env::args().any(|arg| arg == "-k" || arg == "--no-kernel") {
-            args.no_kernel = no_kernel;
-        }
+    if let Some(filter) = config.filter
+        && !std::env::args().any(|arg| arg == "-f" || arg == "--filter")
+    {
+        args.filter = Some(filter);
     }
 
-    if let Some(human_readable) = config.human_readable {
-        if !std::env::args().any(|arg| arg == "-H" || arg == "--human-readable") {
-            args.human_readable = human_readable;
-        }
+    if let Some(expr) = config.expr
+        && !std::env::args().any(|arg| arg == "-e" || arg.starts_with("--expr"))
+    {
+        args.expr = Some(expr);
+    }
+
+    if let Some(user) = config.user
+        && !std::env::args().any(|arg| arg == "-u" || arg == "--user")
+    {
+        args.user = Some(user);
+    }
+
+    if let Some(container) = config.container
+        && !std::env::args().any(|arg| arg.starts_with("--container"))
+    {
+        args.container = Some(container);
+    }
+
+    if let Some(no_kernel) = config.no_kernel
+        && !std::env::args().any(|arg| arg == "-k" || arg == "--no-kernel")
+    {
+        args.no_kernel = no_kernel;
+    }
+
+    if let Some(kernel_only) = config.kernel_only
+        && !std::env::args().any(|arg| arg == "-K" || arg == "--kernel-only")
+    {
+        args.kernel_only = kernel_only;
+    }
+
+    if let Some(human_readable) = config.human_readable
+        && !std::env::args().any(|arg| arg == "-H" || arg == "--human-readable")
+    {
+        args.human_readable = human_readable;
+    }
+
+    if let Some(units) = config.units
+        && !std::env::args().any(|arg| arg.starts_with("--units"))
+    {
+        args.units = units;
+    }
+
+    if let Some(mem_unit) = config.mem_unit
+        && !std::env::args().any(|arg| arg.starts_with("--mem-unit"))
+    {
+        args.mem_unit = Some(mem_unit);
+    }
+
+    if let Some(precision) = config.precision
+        && !std::env::args().any(|arg| arg.starts_with("--precision"))
+    {
+        args.precision = Some(precision);
+    }
+
+    if let Some(compact) = config.compact
+        && !std::env::args().any(|arg| arg == "--compact")
+    {
+        args.compact = compact;
+    }
+
+    if let Some(timezone) = config.timezone
+        && !std::env::args().any(|arg| arg.starts_with("--timezone") || arg == "--utc")
+    {
+        args.timezone = timezone;
     }
     
+    if let Some(tree) = config.tree
+        && !std::env::args().any(|arg| arg == "--tree")
+    {
+        args.tree = tree;
+    }
+
+    if let Some(tree_rollup) = config.tree_rollup
+        && !std::env::args().any(|arg| arg == "--tree-rollup")
+    {
+        args.tree_rollup = tree_rollup;
+    }
+
+    if let Some(group_by) = config.group_by
+        && !std::env::args().any(|arg| arg.starts_with("--group-by"))
+    {
+        args.group_by = Some(group_by);
+    }
+
+    if let Some(no_summary) = config.no_summary
+        && !std::env::args().any(|arg| arg == "--no-summary")
+    {
+        args.no_summary = no_summary;
+    }
+
+    if let Some(columns) = config.columns
+        && !std::env::args().any(|arg| arg == "-c" || arg.starts_with("--columns"))
+    {
+        args.columns = Some(columns);
+    }
+
     // Handle new configuration options
-    if let Some(cpu_above) = config.cpu_above {
-        if !std::env::args().any(|arg| arg == "--cpu-above") {
-            args.cpu_above = Some(cpu_above);
-        }
+    if let Some(cpu_above) = config.cpu_above
+        && !std::env::args().any(|arg| arg == "--cpu-above")
+    {
+        args.cpu_above = Some(cpu_above);
     }
     
-    if let Some(cpu_below) = config.cpu_below {
-        if !std::env::args().any(|arg| arg == "--cpu-below") {
-            args.cpu_below = Some(cpu_below);
-        }
+    if let Some(cpu_below) = config.cpu_below
+        && !std::env::args().any(|arg| arg == "--cpu-below")
+    {
+        args.cpu_below = Some(cpu_below);
     }
     
-    if let Some(mem_above) = config.mem_above {
-        if !std::env::args().any(|arg| arg == "--mem-above") {
-            args.mem_above = Some(mem_above);
-        }
+    if let Some(mem_above) = config.mem_above
+        && !std::env::args().any(|arg| arg == "--mem-above")
+    {
+        args.mem_above = Some(mem_above);
     }
     
-    if let Some(mem_below) = config.mem_below {
-        if !std::env::args().any(|arg| arg == "--mem-below") {
-            args.mem_below = Some(mem_below);
+    if let Some(mem_below) = config.mem_below
+        && !std::env::args().any(|arg| arg == "--mem-below")
+    {
+        args.mem_below = Some(mem_below);
+    }
+
+    if let Some(mem_metric) = config.mem_metric
+        && !std::env::args().any(|arg| arg.starts_with("--mem-metric"))
+    {
+        args.mem_metric = mem_metric;
+    }
+
+    if let Some(number) = args.mem_above.and_then(Size::unitless_number) {
+        args.mem_above = Some(resolve_unitless("mem-above", number, args.human_readable));
+    }
+
+    if let Some(number) = args.mem_below.and_then(Size::unitless_number) {
+        args.mem_below = Some(resolve_unitless("mem-below", number, args.human_readable));
+    }
+
+    if let Some(user) = &args.user {
+        for name in UserFilter::parse(user).unknown_names() {
+            eprintln!("Warning: Unknown user '{}' in user filter", name);
         }
     }
 
+    if let Some(expr) = &args.expr
+        && let Err(e) = Expr::parse(expr)
+    {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("Invalid filter expression: {}", e),
+        ));
+    }
+
     // Create default config file if it doesn't exist
     if let Err(e) = config::ensure_config_file_exists() {
         eprintln!("Warning: Failed to create default config file: {}", e);
@@ -322,16 +957,32 @@ fn main() -> Result<(), Error> {
     if args.generate_config {
         let config_to_save = Config {
             sort_by: Some(args.sort_by),
+            sort: args.sort.clone(),
             refresh_rate: Some(args.refresh_rate),
+            cpu_mode: Some(args.cpu_mode),
             top: args.top,
             filter: args.filter.clone(),
+            expr: args.expr.clone(),
             user: args.user.clone(),
+            container: args.container.clone(),
             no_kernel: Some(args.no_kernel),
+            kernel_only: Some(args.kernel_only),
             human_readable: Some(args.human_readable),
+            units: Some(args.units),
+            mem_unit: args.mem_unit,
+            precision: args.precision,
+            compact: Some(args.compact),
+            timezone: Some(if args.utc { Timezone::Utc } else { args.timezone }),
             cpu_above: args.cpu_above,
             cpu_below: args.cpu_below,
             mem_above: args.mem_above,
             mem_below: args.mem_below,
+            mem_metric: Some(args.mem_metric),
+            tree: Some(args.tree),
+            tree_rollup: Some(args.tree_rollup),
+            group_by: args.group_by,
+            no_summary: Some(args.no_summary),
+            columns: args.columns.clone(),
         };
         
         match config_to_save.save() {
@@ -344,205 +995,197 @@ fn main() -> Result<(), Error> {
                 return Ok(());
             }
             Err(e) => {
-                return Err(Error::new(
-                    ErrorKind::Other,
-                    format!("Failed to save configuration: {}", e),
-                ));
+                return Err(Error::other(format!(
+                    "Failed to save configuration: {}",
+                    e
+                )));
             }
         }
     }
 
-    if args.refresh_rate < 1.0 {
+    if args.refresh_rate < MIN_REFRESH_RATE {
         return Err(Error::new(
-            ErrorKind::InvalidInput,
-            "Refresh rate must be at least 1 second",
+            std::io::ErrorKind::InvalidInput,
+            format!("Refresh rate must be at least {} seconds", MIN_REFRESH_RATE),
         ));
     }
 
-    // Set up panic hook to ensure terminal is restored on panic
-    let default_hook = panic::take_hook();
-    panic::set_hook(Box::new(move |panic_info| {
-        let mut stdout = std::io::stdout();
-        let _ = execute!(stdout, Show, LeaveAlternateScreen);
-        default_hook(panic_info);
-    }));
+    // Recordings hold processes only
+    if args.command.is_some() && args.thread_view() {
+        return Err(Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "--threads and --all-threads can't be recorded or replayed",
+        ));
+    }
 
-    let term = Arc::new(AtomicBool::new(false));
-    signal_hook::flag::register(signal_hook::consts::SIGTERM, Arc::clone(&term))?;
-    signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))?;
+    if let Some(Command::Replay { file }) = &args.command {
+        let file = file.clone();
+        return replay::run(&mut args, &file);
+    }
 
-    // Set up a cleanup handler for signals
-    let cleanup_on_signal = std::thread::spawn({
-        let term = Arc::clone(&term);
-        move || {
-            while !term.load(atomic::Ordering::Relaxed) {
-                std::thread::sleep(Duration::from_millis(100));
+    let mut sys = System::new();
+    sys.refresh_cpu();
+    sys.refresh_memory();
+
+    let mut collector = Collector::new(source::default_source(), sys.cpus().len(), args.cpu_mode);
+
+    if let Some(Command::Record { out }) = &args.command {
+        return record(out, &mut collector, &mut sys, &args);
+    }
+
+    // Non-interactive mode: print to stdout and exit after the requested number of samples
+    if args.batch_iterations().is_some() || args.output != OutputFormat::Table {
+        let mut writer = SnapshotWriter::new(
+            stdout(),
+            args.output,
+            table_columns(&args),
+            format_options(&args),
+        );
+        let mut i = 0;
+
+        // Printed samples always measure CPU over a full interval, so the
+        // first tick only primes the collector
+        sample(&mut collector, &args);
+        let mut ticks = Ticks::new(Instant::now());
+
+        while args.batch_iterations().is_none_or(|iterations| i < iterations) {
+            thread::sleep(ticks.next(args.refresh_rate).saturating_duration_since(Instant::now()));
+            ticks.advance(args.refresh_rate);
+
+            let mut stats = sample(&mut collector, &args);
+            sys.refresh_cpu();
+            sys.refresh_memory();
+            let summary = SystemSummary::collect(&sys, &stats);
+            filter_and_sort(&mut stats, &args);
+
+            if args.output == OutputFormat::Table {
+                let mut stdout = stdout();
+                if i > 0 {
+                    writeln!(stdout)?;
+                }
+                let rows = display_rows(stats, &args, &HashSet::new());
+                print_plain(&mut stdout, &rows, &summary, &args)?;
+            } else {
+                if let Some(top) = args.top {
+                    stats.truncate(top);
+                }
+                writer.write(&Snapshot::new(summary, stats))?;
             }
-            let mut stdout = std::io::stdout();
-            let _ = execute!(stdout, Show, LeaveAlternateScreen);
+
+            i += 1;
         }
-    });
 
-    let mut sys = System::new_all();
-    sys.refresh_all();
-    let num_cpus = sys.cpus().len() as f64;
+        return Ok(());
+    }
 
     let mut stdout = stdout();
+    let (term, cleanup_on_signal) = start_ui(&mut stdout)?;
 
-    let _ = setup_terminal(&mut stdout);
+    let (key_tx, key_rx) = mpsc::channel();
+    input::spawn_reader(key_tx);
 
-    loop {
-        let sample = sample();
+    let mut ui = UiState::new();
 
-        thread::sleep(Duration::from_secs_f64(args.refresh_rate));
+    // Show the first frame right away, with CPU usage averaged over each process's lifetime
+    let mut latest = sample(&mut collector, &args);
+    sys.refresh_memory();
+    let mut summary = SystemSummary::collect(&sys, &latest);
+    let mut visible = render(&mut stdout, &latest, &summary, &args, &mut ui, collector.source());
+    let mut ticks = Ticks::new(Instant::now());
 
-        let mut stats = stats(num_cpus, sample);
-        
-        // Refresh system info before applying filters
-        sys.refresh_all();
-
-        // Apply all filters using functional programming patterns
-        let filters: Vec<Box<dyn Fn(&UsageInfo) -> bool>> = vec![
-            // Filter by name if specified
-            Box::new({
-                let filter_opt = args.filter.clone();
-                move |stat: &UsageInfo| -> bool {
-                    if let Some(filter) = &filter_opt {
-                        let filter_lower = filter.to_lowercase();
-                        stat.name.to_lowercase().contains(&filter_lower)
-                    } else {
-                        true
-                    }
-                }
-            }),
-            
-            // Filter by user if specified
-            Box::new({
-                let user_opt = args.user.clone();
-                // Create a closure that captures system info by value
-                move |stat: &UsageInfo| -> bool {
-                    if let Some(user) = &user_opt {
-                        let user_lower = user.to_lowercase();
-                        // Create a temporary System instance for this check
-                        let mut temp_sys = System::new_with_specifics(
-                            sysinfo::RefreshKind::new().with_processes(sysinfo::ProcessRefreshKind::new())
-                        );
-                        temp_sys.refresh_process(sysinfo::Pid::from_u32(stat.pid));
-                        
-                        if let Some(process) = temp_sys.process(sysinfo::Pid::from_u32(stat.pid)) {
-                            if let Some(uid) = process.user_id() {
-                                uid.to_string().contains(&user_lower)
-                            } else {
-                                false
-                            }
-                        } else {
-                            false
-                        }
-                    } else {
-                        true
+    'main: loop {
+        // Wait for the next tick while reacting to key presses; the deadline is
+        // recomputed each time so changing the refresh rate applies immediately
+        loop {
+            let timeout = ticks.next(args.refresh_rate).saturating_duration_since(Instant::now());
+            match key_rx.recv_timeout(timeout) {
+                Ok(key) => match input::handle_key(key, &mut ui, &mut args, &visible) {
+                    KeyOutcome::Quit => break 'main,
+                    KeyOutcome::Redraw => {
+                        collector.set_mode(args.cpu_mode, &mut latest);
+                        visible = render(&mut stdout, &latest, &summary, &args, &mut ui, collector.source());
                     }
-                }
-            }),
-            
-            // Hide kernel processes if requested
-            Box::new({
-                let no_kernel = args.no_kernel;
-                move |stat: &UsageInfo| -> bool {
-                    if no_kernel {
-                        // Create a temporary System instance for this check
-                        let mut temp_sys = System::new_with_specifics(
-                            sysinfo::RefreshKind::new().with_processes(sysinfo::ProcessRefreshKind::new())
-                        );
-                        temp_sys.refresh_process(sysinfo::Pid::from_u32(stat.pid));
-                        
-                        if let Some(process) = temp_sys.process(sysinfo::Pid::from_u32(stat.pid)) {
-                            !process.name().starts_with("kernel") && stat.pid >= 100
-                        } else {
-                            true
-                        }
-                    } else {
-                        true
+                    KeyOutcome::Resample => {
+                        latest = sample(&mut collector, &args);
+                        summary = SystemSummary::collect(&sys, &latest);
+                        visible = render(&mut stdout, &latest, &summary, &args, &mut ui, collector.source());
                     }
+                    KeyOutcome::None => {}
+                },
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(timeout);
+                    break;
                 }
-            }),
-            
-            // Filter by CPU threshold if specified
-            Box::new({
-                let cpu_above = args.cpu_above;
-                let cpu_below = args.cpu_below;
-                move |stat: &UsageInfo| -> bool {
-                    let above_check = if let Some(threshold) = cpu_above {
-                        stat.cpu > threshold
-                    } else {
-                        true
-                    };
-                    
-                    let below_check = if let Some(threshold) = cpu_below {
-                        stat.cpu < threshold
-                    } else {
-                        true
-                    };
-                    
-                    above_check && below_check
-                }
-            }),
-            
-            // Filter by memory threshold if specified
-            Box::new({
-                let mem_above = args.mem_above;
-                let mem_below = args.mem_below;
-                let human_readable = args.human_readable;
-                move |stat: &UsageInfo| -> bool {
-                    let above_check = if let Some(threshold) = mem_above {
-                        if human_readable {
-                            stat.mem > threshold
-                        } else {
-                            stat.mem > threshold * 1_000_000
-                        }
-                    } else {
-                        true
-                    };
-                    
-                    let below_check = if let Some(threshold) = mem_below {
-                        if human_readable {
-                            stat.mem < threshold
-                        } else {
-                            stat.mem < threshold * 1_000_000
-                        }
-                    } else {
-                        true
-                    };
-                    
-                    above_check && below_check
-                }
-            }),
-        ];
-        
-        // Apply all filters
-        stats.retain(|stat| filters.iter().all(|filter| filter(stat)));
+            }
 
-        // Sort based on the specified criteria
-        match args.sort_by {
-            SortBy::Cpu => {
-                stats.sort_by(|a, b| b.cpu.partial_cmp(&a.cpu).unwrap_or(Ordering::Less))
+            if term.load(atomic::Ordering::Relaxed) {
+                break 'main;
             }
-            SortBy::Memory => stats.sort_by(|a, b| b.mem.cmp(&a.mem)),
-            SortBy::Pid => stats.sort_by(|a, b| a.pid.cmp(&b.pid)),
-            SortBy::StartTime => stats.sort_by(|a, b| a.start_time.cmp(&b.start_time)),
         }
 
-        print(&mut stdout, stats, &args);
+        ticks.advance(args.refresh_rate);
+        latest = sample(&mut collector, &args);
+
+        sys.refresh_cpu();
+        sys.refresh_memory();
+        summary = SystemSummary::collect(&sys, &latest);
+
+        visible = render(&mut stdout, &latest, &summary, &args, &mut ui, collector.source());
 
         if term.load(atomic::Ordering::Relaxed) {
             break;
         }
     }
 
-    let _ = cleanup_terminal(&mut stdout);
+    stop_ui(&mut stdout, &term, cleanup_on_signal);
+    Ok(())
+}
 
-    // Set the termination flag to true and wait for the cleanup thread to finish
-    term.store(true, atomic::Ordering::Relaxed);
-    let _ = cleanup_on_signal.join();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    Ok(())
+    #[test]
+    fn cuts_lines_by_characters() {
+        assert_eq!(cut("python3 -c 'print(\"héllo wörld\")'", 20), "python3 -c 'print(\"h");
+        assert_eq!(cut("python3 -c 'print(\"héllo wörld\")'", 21), "python3 -c 'print(\"hé");
+        assert_eq!(cut("日本語のコマンド", 3), "日本語");
+        assert_eq!(cut("short", 80), "short");
+        assert_eq!(cut("", 0), "");
+    }
+
+    #[test]
+    fn scales_unitless_thresholds_before_rounding() {
+        let (size, explicit) = unitless_size(1.5, false);
+        assert_eq!(size.bytes(), 1_500_000);
+        assert_eq!(explicit, "1.5MB");
+        assert_eq!(explicit.parse::<Size>().unwrap(), size);
+
+        let (size, explicit) = unitless_size(100.0, false);
+        assert_eq!(size.bytes(), 100_000_000);
+        assert_eq!(explicit, "100MB");
+        assert_eq!(explicit.parse::<Size>().unwrap(), size);
+
+        let (size, explicit) = unitless_size(100.0, true);
+        assert_eq!(size.bytes(), 100);
+        assert_eq!(explicit, "100B");
+        assert_eq!(explicit.parse::<Size>().unwrap(), size);
+    }
+
+    #[test]
+    fn tree_rows_fill_the_line() {
+        let args = Args::parse_from(["rustop", "--tree"]);
+        let row = DisplayRow {
+            info: UsageInfo {
+                pid: 1,
+                name: "init".to_string(),
+                ..UsageInfo::default()
+            },
+            prefix: "└─ ".to_string(),
+        };
+        assert_eq!(format_header(&args, 80).chars().count(), 80);
+        assert_eq!(format_row(&row, &args, 80).chars().count(), 80);
+        assert_eq!(format_row(&row, &args, 120).chars().count(), 120);
+    }
 }
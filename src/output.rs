@@ -0,0 +1,187 @@
+//! Machine-readable output formats for non-interactive mode.
+
+use crate::columns::{ColumnSpec, Value, export_memory};
+use crate::format::{FormatOptions, Unit};
+use crate::snapshot::{MemoryUsage, Snapshot, SystemSummary, TaskCounts, UsageInfo};
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::Serialize;
+use serde::ser::{SerializeMap, Serializer};
+use std::io::{self, Write};
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Plain text table
+    Table,
+    /// One pretty-printed JSON document per refresh
+    Json,
+    /// One compact JSON document per line per refresh
+    Ndjson,
+    /// Comma-separated values, one row per process
+    Csv,
+}
+
+/// A process serialized as an object with one entry per selected column, in column order
+struct ProcessRow<'a> {
+    columns: &'a [ColumnSpec],
+    options: &'a FormatOptions,
+    process: &'a UsageInfo,
+}
+
+impl Serialize for ProcessRow<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.columns.len()))?;
+        for spec in self.columns {
+            let value = spec.column.export_value(self.process, self.options);
+            map.serialize_entry(spec.column.key(), &value)?;
+        }
+        map.end()
+    }
+}
+
+/// Memory totals in the exported unit
+#[derive(Serialize)]
+struct ExportedMemory {
+    total: Value,
+    used: Value,
+    available: Value,
+}
+
+impl ExportedMemory {
+    fn new(memory: &MemoryUsage, options: &FormatOptions) -> Self {
+        Self {
+            total: export_memory(memory.total, options),
+            used: export_memory(memory.used, options),
+            available: export_memory(memory.available, options),
+        }
+    }
+}
+
+/// The system summary as exported, with memory in the exported unit
+#[derive(Serialize)]
+struct ExportedSystem<'a> {
+    uptime: u64,
+    load_average: [f64; 3],
+    tasks: &'a TaskCounts,
+    cpus: &'a [f32],
+    memory: ExportedMemory,
+    swap: ExportedMemory,
+}
+
+/// A snapshot as exported, with processes limited to the selected columns
+#[derive(Serialize)]
+struct ExportedSnapshot<'a> {
+    timestamp: &'a DateTime<Utc>,
+    host: &'a str,
+    /// Unit of every memory value, `B` unless one was chosen explicitly
+    memory_unit: &'static str,
+    system: ExportedSystem<'a>,
+    processes: Vec<ProcessRow<'a>>,
+}
+
+fn export<'a>(
+    columns: &'a [ColumnSpec],
+    options: &'a FormatOptions,
+    snapshot: &'a Snapshot,
+) -> ExportedSnapshot<'a> {
+    let system: &SystemSummary = &snapshot.system;
+    ExportedSnapshot {
+        timestamp: &snapshot.timestamp,
+        host: &snapshot.host,
+        memory_unit: options.unit.unwrap_or(Unit::B).label(options.units, false),
+        system: ExportedSystem {
+            uptime: system.uptime,
+            load_average: system.load_average,
+            tasks: &system.tasks,
+            cpus: &system.cpus,
+            memory: ExportedMemory::new(&system.memory, options),
+            swap: ExportedMemory::new(&system.swap, options),
+        },
+        processes: snapshot
+            .processes
+            .iter()
+            .map(|process| ProcessRow {
+                columns,
+                options,
+                process,
+            })
+            .collect(),
+    }
+}
+
+/// Writes snapshots in one of the machine-readable formats
+pub struct SnapshotWriter<W: Write> {
+    out: W,
+    format: OutputFormat,
+    columns: Vec<ColumnSpec>,
+    options: FormatOptions,
+    wrote_header: bool,
+}
+
+impl<W: Write> SnapshotWriter<W> {
+    pub fn new(out: W, format: OutputFormat, columns: Vec<ColumnSpec>, options: FormatOptions) -> Self {
+        Self {
+            out,
+            format,
+            columns,
+            options,
+            wrote_header: false,
+        }
+    }
+
+    /// Write a single snapshot and flush it so consumers see it right away
+    pub fn write(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        match self.format {
+            OutputFormat::Json => {
+                serde_json::to_writer_pretty(&mut self.out, &export(&self.columns, &self.options, snapshot))?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Ndjson => {
+                serde_json::to_writer(&mut self.out, &export(&self.columns, &self.options, snapshot))?;
+                writeln!(self.out)?;
+            }
+            OutputFormat::Csv => self.write_csv(snapshot)?,
+            OutputFormat::Table => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    "table output is printed by the caller",
+                ));
+            }
+        }
+
+        self.out.flush()
+    }
+
+    fn write_csv(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        // The header is written once so consecutive refreshes form a single table
+        if !self.wrote_header {
+            let keys: Vec<&str> = self.columns.iter().map(|spec| spec.column.key()).collect();
+            writeln!(self.out, "timestamp,host,{}", keys.join(","))?;
+            self.wrote_header = true;
+        }
+
+        let timestamp = snapshot.timestamp.to_rfc3339();
+        let host = csv_field(&snapshot.host);
+        for process in &snapshot.processes {
+            let values: Vec<String> = self
+                .columns
+                .iter()
+                .map(|spec| {
+                    csv_field(&spec.column.export_value(process, &self.options).to_string())
+                })
+                .collect();
+            writeln!(self.out, "{},{},{}", timestamp, host, values.join(","))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Quote a CSV field if it contains a separator, quote or line break
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
@@ -0,0 +1,125 @@
+//! Rendering the detail pane that shows everything known about one process.
+
+use rustop::details::parent_chain;
+use rustop::format::{FormatOptions, format_time};
+use rustop::source::ProcessSource;
+
+/// Shown in place of a part of the details that could not be read
+const NOT_AVAILABLE: &str = "  not available";
+
+/// Read `pid` from `source` and format its details as lines of text, with times
+/// judged against `now` in seconds since the epoch
+pub fn lines(source: &dyn ProcessSource, pid: u32, now: u64, options: &FormatOptions) -> Vec<String> {
+    let Some(process) = source.read(pid) else {
+        return vec![format!("Process {} has exited", pid)];
+    };
+    let details = source.details(pid);
+
+    let mut lines = vec![
+        format!("Name:         {}", process.name),
+        // Kernel threads have no command line, the table shows their name instead
+        if process.cmdline.is_empty() {
+            format!("Command line: [{}]", process.name)
+        } else {
+            format!("Command line: {}", process.cmdline)
+        },
+        format!("Executable:   {}", process.exe),
+        format!("Working dir:  {}", details.cwd.as_deref().unwrap_or("not available")),
+        format!("User:         {} ({})", process.user, process.uid),
+        match &process.container {
+            Some(container) => format!("Container:    {} ({})", container.id, container.runtime),
+            None => "Container:    none".to_string(),
+        },
+        format!("Cgroup:       {}", process.cgroup),
+        format!(
+            "State:        {}  Threads: {}  Started: {}",
+            process.state,
+            process.threads,
+            format_time(process.start_time, now, options)
+        ),
+        String::new(),
+        "Parent chain".to_string(),
+    ];
+    for parent in parent_chain(source, &process) {
+        lines.push(format!("  {:>7}  {:<10} {}", parent.pid, parent.user, parent.name));
+    }
+
+    let limits = details.limits.map(|limits| {
+        limits
+            .iter()
+            .map(|limit| {
+                format!(
+                    "  {:<26} {:>20} {:>20}  {}",
+                    limit.name, limit.soft, limit.hard, limit.units
+                )
+            })
+            .collect()
+    });
+    push_section(&mut lines, "Limits", limits);
+
+    let files = details.files.map(|files| {
+        files
+            .iter()
+            .map(|file| {
+                // The target is empty when the descriptor couldn't be resolved
+                let target = if file.target.is_empty() { "unknown" } else { &file.target };
+                format!("  {:>5}  {}", file.fd, target)
+            })
+            .collect()
+    });
+    push_section(&mut lines, "Open files", files);
+
+    let cgroups = details
+        .cgroups
+        .map(|cgroups| cgroups.iter().map(|cgroup| format!("  {}", cgroup)).collect());
+    push_section(&mut lines, "Control groups", cgroups);
+
+    let namespaces = details.namespaces.map(|namespaces| {
+        namespaces
+            .iter()
+            .map(|namespace| format!("  {:<18} {}", namespace.kind, namespace.id))
+            .collect()
+    });
+    push_section(&mut lines, "Namespaces", namespaces);
+
+    let environment = details
+        .environment
+        .map(|environment| environment.iter().map(|variable| format!("  {}", variable)).collect());
+    push_section(&mut lines, "Environment", environment);
+
+    lines
+}
+
+/// Append a titled section with the number of entries in the title, or a note
+/// if the entries could not be read
+fn push_section(lines: &mut Vec<String>, title: &str, entries: Option<Vec<String>>) {
+    lines.push(String::new());
+    match entries {
+        Some(entries) => {
+            lines.push(format!("{} ({})", title, entries.len()));
+            lines.extend(entries);
+        }
+        None => {
+            lines.push(title.to_string());
+            lines.push(NOT_AVAILABLE.to_string());
+        }
+    }
+}
+
+/// Break lines longer than `width` characters into several, so nothing is cut off
+pub fn wrap(lines: &[String], width: usize) -> Vec<String> {
+    let width = width.max(1);
+    lines
+        .iter()
+        .flat_map(|line| {
+            let chars: Vec<char> = line.chars().collect();
+            if chars.is_empty() {
+                return vec![String::new()];
+            }
+            chars
+                .chunks(width)
+                .map(|chunk| chunk.iter().collect())
+                .collect()
+        })
+        .collect()
+}
@@ -0,0 +1,456 @@
+//! Recording snapshots to a file and reading them back for replay.
+//!
+//! A recording is NDJSON: a header line naming the format and its version,
+//! followed by one `Snapshot` per line with every process and the system
+//! summary, unfiltered, so a replay can filter, sort and group them like a
+//! live view. CPU usage and I/O rates are not written but worked out from
+//! consecutive snapshots on replay. Recordings are appended to, so one file
+//! can hold several sessions.
+
+use crate::collector::CpuMode;
+use crate::details::ProcessDetails;
+use crate::snapshot::{Snapshot, UsageInfo};
+use crate::source::ProcessSource;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::Path;
+
+/// Name in the header that marks a file as a recording
+const FORMAT: &str = "rustop-recording";
+
+/// Version of the format written; bumped when a change would confuse older readers
+pub const VERSION: u32 = 1;
+
+/// First line of a recording
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Header {
+    /// Always `rustop-recording`
+    pub format: String,
+    pub version: u32,
+    /// What 100% CPU stands for in the recorded processes
+    pub cpu_mode: CpuMode,
+}
+
+impl Header {
+    /// Read the header from the first line of `path`, checking that it is a
+    /// recording this version can read
+    fn parse(line: &str, path: &Path) -> io::Result<Self> {
+        let invalid = |message: String| io::Error::new(io::ErrorKind::InvalidData, message);
+
+        let header: Header = serde_json::from_str(line)
+            .ok()
+            .filter(|header: &Header| header.format == FORMAT)
+            .ok_or_else(|| invalid(format!("{} is not a rustop recording", path.display())))?;
+        if header.version != VERSION {
+            return Err(invalid(format!(
+                "{} is a version {} recording, this rustop reads version {}",
+                path.display(),
+                header.version,
+                VERSION
+            )));
+        }
+        Ok(header)
+    }
+}
+
+/// Appends snapshots to a recording
+pub struct Recorder {
+    out: File,
+    header: Header,
+}
+
+impl Recorder {
+    /// Open the recording at `path` for appending, or create it with a header
+    /// for processes measured in `cpu_mode`.
+    ///
+    /// An existing recording keeps its header, so the caller has to record in
+    /// the CPU mode of `header()` rather than the one asked for.
+    pub fn open(path: &Path, cpu_mode: CpuMode) -> io::Result<Self> {
+        let mut out = OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|e| with_path(e, path))?;
+
+        if out.metadata()?.len() == 0 {
+            let header = Header {
+                format: FORMAT.to_string(),
+                version: VERSION,
+                cpu_mode,
+            };
+            let mut line = serde_json::to_vec(&header)?;
+            line.push(b'\n');
+            out.write_all(&line)?;
+            return Ok(Self { out, header });
+        }
+
+        let mut first = String::new();
+        BufReader::new(&out).read_line(&mut first)?;
+        let header = Header::parse(&first, path)?;
+
+        // A recorder that was killed mid-line leaves it unfinished; end it so the
+        // next snapshot starts on a line of its own
+        let mut last = [0];
+        out.seek(SeekFrom::End(-1))?;
+        out.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            out.write_all(b"\n")?;
+        }
+
+        Ok(Self { out, header })
+    }
+
+    pub fn header(&self) -> &Header {
+        &self.header
+    }
+
+    /// Append `snapshot` as a single write, so an interrupted recorder never
+    /// leaves half a line behind
+    pub fn write(&mut self, snapshot: &Snapshot) -> io::Result<()> {
+        let mut line = serde_json::to_vec(snapshot)?;
+        line.push(b'\n');
+        self.out.write_all(&line)
+    }
+}
+
+/// `error` with the path of the file it is about in front
+fn with_path(error: io::Error, path: &Path) -> io::Error {
+    io::Error::new(error.kind(), format!("{}: {}", path.display(), error))
+}
+
+/// Just the timestamp of a snapshot, to index a recording without keeping it in memory
+#[derive(Deserialize)]
+struct Stamp {
+    timestamp: DateTime<Utc>,
+}
+
+/// Where a snapshot starts in the file
+struct Frame {
+    timestamp: DateTime<Utc>,
+    offset: u64,
+}
+
+/// A recording opened for replay.
+///
+/// Only the timestamp and position of each snapshot are kept in memory; a
+/// snapshot is read from the file when it is shown, so recordings of a whole
+/// night don't have to fit in memory.
+pub struct Recording {
+    pub header: Header,
+    file: BufReader<File>,
+    /// Snapshots in the order they were taken
+    frames: Vec<Frame>,
+    /// Lines that could not be read, such as one cut short by a crash
+    pub damaged: usize,
+}
+
+impl Recording {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let mut file = BufReader::new(File::open(path).map_err(|e| with_path(e, path))?);
+
+        let mut line = String::new();
+        let mut offset = file.read_line(&mut line)? as u64;
+        let header = Header::parse(&line, path)?;
+
+        let mut frames = Vec::new();
+        let mut damaged = 0;
+        loop {
+            line.clear();
+            let length = file.read_line(&mut line)?;
+            if length == 0 {
+                break;
+            }
+
+            if !line.trim().is_empty() {
+                match serde_json::from_str::<Stamp>(&line) {
+                    Ok(stamp) => frames.push(Frame {
+                        timestamp: stamp.timestamp,
+                        offset,
+                    }),
+                    Err(_) => damaged += 1,
+                }
+            }
+            offset += length as u64;
+        }
+
+        // Sessions appended after the clock was set back would otherwise be out of order
+        frames.sort_by_key(|frame| frame.timestamp);
+
+        Ok(Self {
+            header,
+            file,
+            frames,
+            damaged,
+        })
+    }
+
+    /// Number of snapshots
+    pub fn len(&self) -> usize {
+        self.frames.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frames.is_empty()
+    }
+
+    /// When each snapshot was taken, in order
+    pub fn timestamps(&self) -> Vec<DateTime<Utc>> {
+        self.frames.iter().map(|frame| frame.timestamp).collect()
+    }
+
+    /// Read the snapshot at `index`, counted from the earliest, with CPU usage
+    /// and I/O rates measured against the snapshot before it
+    pub fn frame(&mut self, index: usize) -> io::Result<Snapshot> {
+        let mut snapshot = self.read(index)?;
+        // Without a readable snapshot before, usage is averaged over each
+        // process's lifetime, like the first live sample
+        let previous = match index.checked_sub(1) {
+            Some(before) => self.read(before).ok(),
+            None => None,
+        };
+        measure(&mut snapshot, previous.as_ref(), self.header.cpu_mode);
+        Ok(snapshot)
+    }
+
+    /// Read the snapshot at `index` as it was written
+    fn read(&mut self, index: usize) -> io::Result<Snapshot> {
+        let frame = self.frames.get(index).ok_or_else(|| {
+            io::Error::new(io::ErrorKind::InvalidInput, format!("no snapshot {} in the recording", index))
+        })?;
+
+        self.file.seek(SeekFrom::Start(frame.offset))?;
+        let mut line = String::new();
+        self.file.read_line(&mut line)?;
+        serde_json::from_str(&line).map_err(io::Error::from)
+    }
+}
+
+/// Fill in what a recording leaves out of the processes of `snapshot`: CPU
+/// usage in `mode` and I/O rates since `previous`, or since the process
+/// started if it isn't in there, the time they were read and their count
+fn measure(snapshot: &mut Snapshot, previous: Option<&Snapshot>, mode: CpuMode) {
+    let sampled_at = snapshot.timestamp.timestamp().max(0) as u64;
+    let since_previous = previous
+        .map(|previous| (snapshot.timestamp - previous.timestamp).num_milliseconds() as f64 / 1000.0);
+    let baselines: HashMap<u32, &UsageInfo> = previous
+        .map(|previous| previous.processes.iter().map(|process| (process.pid, process)).collect())
+        .unwrap_or_default();
+    let scale = 100.0 * CpuMode::Irix.factor(mode, snapshot.system.cpus.len().max(1) as f64);
+
+    for process in &mut snapshot.processes {
+        process.count = 1;
+        process.sampled_at = sampled_at;
+
+        // A reused PID belongs to a different process with its own start time
+        let baseline = baselines
+            .get(&process.pid)
+            .filter(|baseline| baseline.start_time == process.start_time);
+        let interval = match (baseline, since_previous) {
+            (Some(_), Some(interval)) => interval,
+            _ => sampled_at.saturating_sub(process.start_time) as f64,
+        };
+        let per_second = |used: f64| {
+            if interval > 0.0 { used.max(0.0) / interval } else { 0.0 }
+        };
+
+        let cpu_time = baseline.map_or(0.0, |baseline| baseline.cpu_time);
+        let read_bytes = baseline.map_or(0, |baseline| baseline.read_bytes);
+        let write_bytes = baseline.map_or(0, |baseline| baseline.write_bytes);
+        process.cpu = per_second(process.cpu_time - cpu_time) * scale;
+        process.read_rate = per_second(process.read_bytes.saturating_sub(read_bytes) as f64);
+        process.write_rate = per_second(process.write_bytes.saturating_sub(write_bytes) as f64);
+    }
+}
+
+/// The processes of a recorded snapshot as a process source, so parent chains
+/// and the detail pane work in a replay.
+///
+/// Threads and the details beyond `UsageInfo` are not recorded, so they read
+/// as empty.
+pub struct RecordedSource<'a> {
+    processes: &'a [UsageInfo],
+}
+
+impl<'a> RecordedSource<'a> {
+    pub fn new(processes: &'a [UsageInfo]) -> Self {
+        Self { processes }
+    }
+}
+
+impl ProcessSource for RecordedSource<'_> {
+    fn pids(&self) -> Vec<u32> {
+        self.processes.iter().map(|process| process.pid).collect()
+    }
+
+    fn read(&self, pid: u32) -> Option<UsageInfo> {
+        self.processes.iter().find(|process| process.pid == pid).cloned()
+    }
+
+    fn read_threads(&self, _pid: u32) -> Vec<UsageInfo> {
+        Vec::new()
+    }
+
+    fn read_memory_breakdown(&self, _process: &mut UsageInfo) {}
+
+    fn details(&self, _pid: u32) -> ProcessDetails {
+        ProcessDetails::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cgroup::Runtime;
+    use crate::group::{self, GroupBy};
+    use crate::snapshot::SystemSummary;
+    use std::path::PathBuf;
+
+    /// A recording file of its own for each test, removed when dropped
+    struct TempRecording(PathBuf);
+
+    impl TempRecording {
+        fn new(name: &str) -> Self {
+            let path = std::env::temp_dir().join(format!("rustop-{}-{}.ndjson", name, std::process::id()));
+            let _ = std::fs::remove_file(&path);
+            Self(path)
+        }
+    }
+
+    impl Drop for TempRecording {
+        fn drop(&mut self) {
+            let _ = std::fs::remove_file(&self.0);
+        }
+    }
+
+    fn snapshot(seconds: i64, processes: Vec<UsageInfo>) -> Snapshot {
+        Snapshot {
+            timestamp: DateTime::from_timestamp(seconds, 0).unwrap(),
+            host: "test".to_string(),
+            system: SystemSummary {
+                cpus: vec![0.0; 4],
+                ..SystemSummary::default()
+            },
+            processes,
+        }
+    }
+
+    fn process(pid: u32, start_time: u64, cpu_time: f64, read_bytes: u64) -> UsageInfo {
+        UsageInfo {
+            pid,
+            tgid: pid,
+            name: format!("process{}", pid),
+            state: 'S',
+            start_time,
+            cpu_time,
+            read_bytes,
+            ..UsageInfo::default()
+        }
+    }
+
+    #[test]
+    fn replays_what_was_recorded() {
+        let file = TempRecording::new("round-trip");
+        let mut recorder = Recorder::open(&file.0, CpuMode::Irix).unwrap();
+        recorder.write(&snapshot(1_000, vec![process(1, 990, 5.0, 0)])).unwrap();
+        recorder
+            .write(&snapshot(1_002, vec![process(1, 990, 6.0, 4096), process(2, 1_001, 0.5, 0)]))
+            .unwrap();
+        drop(recorder);
+
+        // Reopening keeps the mode the recording was started in
+        let recorder = Recorder::open(&file.0, CpuMode::Solaris).unwrap();
+        assert_eq!(recorder.header().cpu_mode, CpuMode::Irix);
+
+        let mut recording = Recording::open(&file.0).unwrap();
+        assert_eq!(recording.len(), 2);
+        assert_eq!(recording.damaged, 0);
+        assert_eq!(
+            recording.timestamps(),
+            vec![DateTime::from_timestamp(1_000, 0).unwrap(), DateTime::from_timestamp(1_002, 0).unwrap()]
+        );
+
+        // The first snapshot is averaged over the process's lifetime
+        let first = recording.frame(0).unwrap();
+        assert_eq!(first.processes[0].cpu, 50.0);
+        assert_eq!(first.processes[0].sampled_at, 1_000);
+        assert_eq!(first.processes[0].count, 1);
+
+        let second = recording.frame(1).unwrap();
+        let (old, new) = (&second.processes[0], &second.processes[1]);
+        assert_eq!(old.name, "process1");
+        assert_eq!(old.cpu, 50.0);
+        assert_eq!(old.read_rate, 2048.0);
+        assert_eq!(new.cpu, 50.0);
+        assert_eq!(new.sampled_at, 1_002);
+    }
+
+    #[test]
+    fn leaves_derived_and_default_fields_out() {
+        let line = serde_json::to_string(&UsageInfo {
+            cpu: 12.5,
+            count: 3,
+            sampled_at: 1_000,
+            read_rate: 1.0,
+            ..process(7, 990, 0.0, 0)
+        })
+        .unwrap();
+        assert_eq!(line, r#"{"pid":7,"tgid":7,"ppid":0,"uid":0,"user":"","name":"process7","state":"S","threads":0,"start_time":990}"#);
+    }
+
+    #[test]
+    fn skips_lines_cut_short() {
+        let file = TempRecording::new("damaged");
+        let mut recorder = Recorder::open(&file.0, CpuMode::Irix).unwrap();
+        recorder.write(&snapshot(1_000, vec![process(1, 990, 5.0, 0)])).unwrap();
+        recorder.out.write_all(br#"{"timestamp":"1970-01-01T00:16:42Z","host":"#).unwrap();
+        drop(recorder);
+
+        // The unfinished line is ended before the next snapshot is appended
+        let mut recorder = Recorder::open(&file.0, CpuMode::Irix).unwrap();
+        recorder.write(&snapshot(1_004, vec![process(1, 990, 7.0, 0)])).unwrap();
+
+        let mut recording = Recording::open(&file.0).unwrap();
+        assert_eq!(recording.len(), 2);
+        assert_eq!(recording.damaged, 1);
+        assert_eq!(recording.frame(1).unwrap().processes[0].cpu, 50.0);
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        let file = TempRecording::new("not-a-recording");
+        std::fs::write(&file.0, "{\"timestamp\":1}\n").unwrap();
+        let error = Recording::open(&file.0).err().unwrap();
+        assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+        assert!(Recorder::open(&file.0, CpuMode::Irix).is_err());
+    }
+
+    #[test]
+    fn reads_short_and_garbage_container_ids() {
+        let file = TempRecording::new("container-ids");
+        let header = format!(r#"{{"format":"{}","version":{},"cpu_mode":"irix"}}"#, FORMAT, VERSION);
+        let processes = [(1, "abc"), (2, "ab€€€€"), (3, "")]
+            .map(|(pid, id)| format!(r#"{{"pid":{},"container":{{"runtime":"docker","id":"{}"}}}}"#, pid, id))
+            .join(",");
+        let line = format!(
+            r#"{{"timestamp":"2024-01-01T00:00:00Z","host":"","system":{},"processes":[{}]}}"#,
+            serde_json::to_string(&SystemSummary::default()).unwrap(),
+            processes
+        );
+        std::fs::write(&file.0, format!("{}\n{}\n", header, line)).unwrap();
+
+        let mut recording = Recording::open(&file.0).unwrap();
+        let snapshot = recording.frame(0).unwrap();
+        let ids: Vec<&str> = snapshot
+            .processes
+            .iter()
+            .map(|process| process.container.as_ref().unwrap().short_id())
+            .collect();
+        assert_eq!(ids, ["abc", "ab€€€€", ""]);
+        assert_eq!(snapshot.processes[0].container.as_ref().unwrap().runtime, Runtime::Docker);
+        assert_eq!(group::group(snapshot.processes, GroupBy::Container).len(), 3);
+    }
+}
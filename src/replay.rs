@@ -0,0 +1,299 @@
+//! Playing back a recording in the interactive UI.
+
+use crate::input::{self, KeyOutcome, UiState};
+use crate::{Args, Ticks, render, start_ui, stop_ui};
+use chrono::{DateTime, NaiveDateTime, NaiveTime, TimeDelta, Utc};
+use rustop::collector::CpuMode;
+use rustop::format::Timezone;
+use rustop::recording::{RecordedSource, Recording};
+use rustop::snapshot::Snapshot;
+use std::io::{Error, ErrorKind, Stdout, stdout};
+use std::path::Path;
+use std::sync::atomic;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread;
+use std::time::Instant;
+
+/// What the seek prompt accepts
+pub const SEEK_FORMATS: &str = "HH:MM[:SS], YYYY-MM-DD HH:MM[:SS] or an offset like +90s, -5m or +1h";
+
+/// Position in a recording being replayed
+pub struct Playback {
+    /// When each snapshot was taken, in order
+    times: Vec<DateTime<Utc>>,
+    /// Index of the snapshot shown
+    pub frame: usize,
+    pub paused: bool,
+}
+
+impl Playback {
+    pub fn new(times: Vec<DateTime<Utc>>) -> Self {
+        Self {
+            times,
+            frame: 0,
+            paused: false,
+        }
+    }
+
+    /// Move on to the next snapshot unless paused, pausing at the last one;
+    /// returns whether there is anything new to draw
+    fn advance(&mut self) -> bool {
+        if self.paused {
+            return false;
+        }
+        if self.frame + 1 >= self.times.len() {
+            self.paused = true;
+        } else {
+            self.frame += 1;
+        }
+        true
+    }
+
+    /// Step `delta` snapshots back or forward and pause there
+    pub fn step(&mut self, delta: isize) {
+        self.frame = self
+            .frame
+            .saturating_add_signed(delta)
+            .min(self.times.len().saturating_sub(1));
+        self.paused = true;
+    }
+
+    /// Pause or resume; resuming at the end plays the recording from the start
+    pub fn toggle_pause(&mut self) {
+        if self.paused && self.frame + 1 >= self.times.len() {
+            self.frame = 0;
+        }
+        self.paused = !self.paused;
+    }
+
+    /// Jump to the last snapshot taken at or before the moment `text` names,
+    /// or the first one if the recording starts later
+    pub fn seek(&mut self, text: &str, timezone: Timezone) -> Result<(), String> {
+        let target = self.target(text, timezone)?;
+        self.frame = self.times.partition_point(|&time| time <= target).saturating_sub(1);
+        Ok(())
+    }
+
+    /// The moment `text` names: a time of day on the day of the shown snapshot,
+    /// a date and time, both in `timezone`, or an offset from the shown snapshot
+    pub fn target(&self, text: &str, timezone: Timezone) -> Result<DateTime<Utc>, String> {
+        let text = text.trim();
+        let current = self.times[self.frame];
+        let invalid = || format!("invalid time '{}', expected {}", text, SEEK_FORMATS);
+
+        if let Some(sign @ ('+' | '-')) = text.chars().next() {
+            let amount = &text[1..];
+            let digits = amount.find(|c: char| !c.is_ascii_digit()).unwrap_or(amount.len());
+            let (number, unit) = amount.split_at(digits);
+            let unit_seconds = match unit {
+                "" | "s" => 1,
+                "m" => 60,
+                "h" => 3_600,
+                _ => return Err(invalid()),
+            };
+            let seconds = number.parse::<i64>().map_err(|_| invalid())?.saturating_mul(unit_seconds);
+            let offset = TimeDelta::try_seconds(seconds).ok_or_else(invalid)?;
+            return Ok(if sign == '+' { current + offset } else { current - offset });
+        }
+
+        let naive = ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M"]
+            .iter()
+            .find_map(|pattern| NaiveDateTime::parse_from_str(text, pattern).ok())
+            .or_else(|| {
+                let time = ["%H:%M:%S", "%H:%M"]
+                    .iter()
+                    .find_map(|pattern| NaiveTime::parse_from_str(text, pattern).ok())?;
+                Some(timezone.naive(current).date().and_time(time))
+            })
+            .ok_or_else(invalid)?;
+        timezone
+            .resolve(naive)
+            .ok_or_else(|| format!("{} is skipped by the clock change in this time zone", text))
+    }
+
+    /// Where the replay stands, for the status line
+    pub fn status(&self, timezone: Timezone) -> String {
+        format!(
+            "Replay {} ({}/{}{})",
+            timezone.naive(self.times[self.frame]).format("%Y-%m-%d %H:%M:%S"),
+            self.frame + 1,
+            self.times.len(),
+            if self.paused { ", paused" } else { "" }
+        )
+    }
+}
+
+/// Filter, sort and draw `snapshot` like a live sample, with its CPU usage
+/// converted from `recorded_mode` to the mode in `args`
+fn draw(
+    stdout: &mut Stdout,
+    snapshot: &Snapshot,
+    recorded_mode: CpuMode,
+    args: &Args,
+    ui: &mut UiState,
+) -> Vec<u32> {
+    let mut processes = snapshot.processes.clone();
+    let factor = recorded_mode.factor(args.cpu_mode, snapshot.system.cpus.len().max(1) as f64);
+    for process in &mut processes {
+        process.cpu *= factor;
+    }
+
+    let source = RecordedSource::new(&processes);
+    render(stdout, &processes, &snapshot.system, args, ui, &source)
+}
+
+/// Read the snapshot the playback is at into `snapshot` if it isn't the one
+/// at `shown`, reporting a damaged one in the status line instead
+fn load(recording: &mut Recording, ui: &mut UiState, shown: &mut usize, snapshot: &mut Snapshot) {
+    let Some(frame) = ui.playback.as_ref().map(|playback| playback.frame) else {
+        return;
+    };
+    if frame == *shown {
+        return;
+    }
+
+    match recording.frame(frame) {
+        Ok(frame) => *snapshot = frame,
+        Err(e) => ui.message = Some(format!("Failed to read snapshot {}: {}", frame + 1, e)),
+    }
+    *shown = frame;
+}
+
+/// Play back the recording at `path`, one snapshot per refresh interval, until quit
+pub fn run(args: &mut Args, path: &Path) -> Result<(), Error> {
+    let mut recording = Recording::open(path)?;
+    if recording.is_empty() {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            format!("{} holds no snapshots", path.display()),
+        ));
+    }
+    let recorded_mode = recording.header.cpu_mode;
+    let mut snapshot = recording.frame(0)?;
+    let mut shown = 0;
+
+    let mut stdout = stdout();
+    let (term, cleanup_on_signal) = start_ui(&mut stdout)?;
+
+    let (key_tx, key_rx) = mpsc::channel();
+    input::spawn_reader(key_tx);
+
+    let mut ui = UiState::new();
+    ui.playback = Some(Playback::new(recording.timestamps()));
+    if recording.damaged > 0 {
+        ui.message = Some(format!("Skipped {} damaged lines of {}", recording.damaged, path.display()));
+    }
+
+    let mut visible = draw(&mut stdout, &snapshot, recorded_mode, args, &mut ui);
+    let mut ticks = Ticks::new(Instant::now());
+
+    'main: loop {
+        loop {
+            let timeout = ticks.next(args.refresh_rate).saturating_duration_since(Instant::now());
+            match key_rx.recv_timeout(timeout) {
+                Ok(key) => match input::handle_key(key, &mut ui, args, &visible) {
+                    KeyOutcome::Quit => break 'main,
+                    // Nothing is read live, so a new sample is the shown snapshot again
+                    KeyOutcome::Redraw | KeyOutcome::Resample => {
+                        load(&mut recording, &mut ui, &mut shown, &mut snapshot);
+                        visible = draw(&mut stdout, &snapshot, recorded_mode, args, &mut ui);
+                    }
+                    KeyOutcome::None => {}
+                },
+                Err(RecvTimeoutError::Timeout) => break,
+                Err(RecvTimeoutError::Disconnected) => {
+                    thread::sleep(timeout);
+                    break;
+                }
+            }
+
+            if term.load(atomic::Ordering::Relaxed) {
+                break 'main;
+            }
+        }
+
+        ticks.advance(args.refresh_rate);
+        if ui.playback.as_mut().is_some_and(Playback::advance) {
+            load(&mut recording, &mut ui, &mut shown, &mut snapshot);
+            visible = draw(&mut stdout, &snapshot, recorded_mode, args, &mut ui);
+        }
+
+        if term.load(atomic::Ordering::Relaxed) {
+            break;
+        }
+    }
+
+    stop_ui(&mut stdout, &term, cleanup_on_signal);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rustop::recording::Recorder;
+    use rustop::snapshot::{SystemSummary, UsageInfo};
+
+    #[test]
+    fn plays_back_a_recording() {
+        let path = std::env::temp_dir().join(format!("rustop-playback-{}.ndjson", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        // Snapshots at 10:00:00, 10:00:05 and 10:00:10 UTC
+        let start = DateTime::parse_from_rfc3339("2024-03-01T10:00:00Z").unwrap().to_utc();
+        let mut recorder = Recorder::open(&path, CpuMode::Irix).unwrap();
+        for step in 0..3 {
+            let process = UsageInfo {
+                pid: 42,
+                tgid: 42,
+                name: format!("step{}", step),
+                ..UsageInfo::default()
+            };
+            recorder
+                .write(&Snapshot {
+                    timestamp: start + TimeDelta::seconds(5 * step),
+                    host: String::new(),
+                    system: SystemSummary::default(),
+                    processes: vec![process],
+                })
+                .unwrap();
+        }
+
+        let mut recording = Recording::open(&path).unwrap();
+        let mut playback = Playback::new(recording.timestamps());
+        let shown = |recording: &mut Recording, playback: &Playback| {
+            recording.frame(playback.frame).unwrap().processes[0].name.clone()
+        };
+        assert_eq!(shown(&mut recording, &playback), "step0");
+
+        assert!(playback.advance());
+        assert_eq!(shown(&mut recording, &playback), "step1");
+        assert_eq!(playback.status(Timezone::Utc), "Replay 2024-03-01 10:00:05 (2/3)");
+
+        // The last snapshot pauses the playback, resuming starts over
+        assert!(playback.advance());
+        assert!(playback.advance());
+        assert!(playback.paused);
+        assert_eq!(shown(&mut recording, &playback), "step2");
+        assert!(!playback.advance());
+        playback.toggle_pause();
+        assert_eq!(playback.frame, 0);
+
+        playback.step(-1);
+        assert_eq!(playback.frame, 0);
+        playback.step(5);
+        assert_eq!(playback.frame, 2);
+
+        playback.seek("10:00:07", Timezone::Utc).unwrap();
+        assert_eq!(shown(&mut recording, &playback), "step1");
+        playback.seek("-5s", Timezone::Utc).unwrap();
+        assert_eq!(playback.frame, 0);
+        playback.seek("+1m", Timezone::Utc).unwrap();
+        assert_eq!(playback.frame, 2);
+        playback.seek("2024-03-01 09:00", Timezone::Utc).unwrap();
+        assert_eq!(playback.frame, 0);
+        assert!(playback.seek("yesterday", Timezone::Utc).is_err());
+        assert!(playback.seek("+5d", Timezone::Utc).is_err());
+
+        let _ = std::fs::remove_file(&path);
+    }
+}
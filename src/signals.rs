@@ -0,0 +1,30 @@
+//! Sending signals to processes from the interactive UI.
+
+use std::io;
+
+/// Signals offered in the signal menu, in display order
+pub const SIGNALS: &[(&str, i32)] = &[
+    ("SIGTERM", libc::SIGTERM),
+    ("SIGKILL", libc::SIGKILL),
+    ("SIGSTOP", libc::SIGSTOP),
+    ("SIGCONT", libc::SIGCONT),
+    ("SIGHUP", libc::SIGHUP),
+    ("SIGUSR1", libc::SIGUSR1),
+    ("SIGUSR2", libc::SIGUSR2),
+];
+
+/// Send `signal` to the process `pid`
+pub fn send(pid: u32, signal: i32) -> io::Result<()> {
+    // kill(2) treats 0 and negative PIDs as process groups, never target those
+    let pid = i32::try_from(pid)
+        .ok()
+        .filter(|&pid| pid > 0)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "invalid PID"))?;
+
+    // SAFETY: kill has no memory safety preconditions
+    if unsafe { libc::kill(pid, signal) } == 0 {
+        Ok(())
+    } else {
+        Err(io::Error::last_os_error())
+    }
+}
@@ -0,0 +1,196 @@
+//! Amounts of memory written with units, such as `512M` or `1.5GiB`.
+
+use serde::{Deserialize, Deserializer, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+/// Binary units used when formatting a size, largest first
+const BINARY_UNITS: &[(&str, u64)] = &[
+    ("T", 1 << 40),
+    ("G", 1 << 30),
+    ("M", 1 << 20),
+    ("K", 1 << 10),
+];
+
+/// Multiplier for a unit suffix, case-insensitive.
+///
+/// `K`, `M`, `G` and `T`, optionally followed by `i` or `iB`, are powers of
+/// 1024. `KB`, `MB`, `GB` and `TB` are powers of 1000. `B` and the empty
+/// suffix are bytes.
+pub fn multiplier(suffix: &str) -> Option<u64> {
+    let suffix = suffix.to_ascii_uppercase();
+    let (prefix, decimal) = match suffix.as_str() {
+        "" | "B" => return Some(1),
+        s if s.ends_with("IB") => (&s[..s.len() - 2], false),
+        s if s.ends_with('I') => (&s[..s.len() - 1], false),
+        s if s.len() == 2 && s.ends_with('B') => (&s[..1], true),
+        s => (s, false),
+    };
+
+    let exponent = match prefix {
+        "K" => 1,
+        "M" => 2,
+        "G" => 3,
+        "T" => 4,
+        _ => return None,
+    };
+    let base: u64 = if decimal { 1000 } else { 1024 };
+    Some(base.pow(exponent))
+}
+
+/// A number of bytes parsed from a string with a unit.
+///
+/// A bare number is still accepted for older configs and command lines but is
+/// kept as written, so the caller can warn and decide what it means.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Size {
+    bytes: u64,
+    /// The number as written if it had no unit, unrounded so it can be scaled
+    unitless: Option<f64>,
+}
+
+impl Size {
+    pub fn from_bytes(bytes: u64) -> Self {
+        Self {
+            bytes,
+            unitless: None,
+        }
+    }
+
+    /// The size in bytes, or the bare number if it was written without a unit
+    pub fn bytes(self) -> u64 {
+        self.bytes
+    }
+
+    /// Whether the size was written as a bare number
+    pub fn is_unitless(self) -> bool {
+        self.unitless.is_some()
+    }
+
+    /// The bare number the size was written as, or `None` if it had a unit
+    pub fn unitless_number(self) -> Option<f64> {
+        self.unitless
+    }
+}
+
+impl FromStr for Size {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let text = text.trim();
+        let split = text
+            .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+            .unwrap_or(text.len());
+        let (number, suffix) = text.split_at(split);
+
+        let number: f64 = number
+            .parse()
+            .map_err(|_| format!("invalid size '{}', expected a number with a unit like 512M", text))?;
+        let multiplier = multiplier(suffix.trim()).ok_or_else(|| {
+            format!(
+                "unknown unit '{}' in size '{}', expected B, K/KiB, M/MiB, G/GiB, T/TiB or KB/MB/GB/TB",
+                suffix.trim(),
+                text
+            )
+        })?;
+
+        Ok(Self {
+            bytes: (number * multiplier as f64).round() as u64,
+            unitless: suffix.is_empty().then_some(number),
+        })
+    }
+}
+
+impl fmt::Display for Size {
+    /// The largest binary unit that represents the size exactly, e.g. `512M`
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match BINARY_UNITS
+            .iter()
+            .find(|(_, factor)| self.bytes != 0 && self.bytes.is_multiple_of(*factor))
+        {
+            Some((unit, factor)) => write!(f, "{}{}", self.bytes / factor, unit),
+            None => write!(f, "{}B", self.bytes),
+        }
+    }
+}
+
+impl Serialize for Size {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Size {
+    /// Accepts strings with units and, from older config files, bare integers
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(bytes) => Ok(Self {
+                bytes,
+                unitless: Some(bytes as f64),
+            }),
+            Raw::Text(text) => text.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(text: &str) -> u64 {
+        text.parse::<Size>().unwrap().bytes()
+    }
+
+    #[test]
+    fn parses_binary_and_decimal_units() {
+        assert_eq!(bytes("512B"), 512);
+        assert_eq!(bytes("4K"), 4 * 1024);
+        assert_eq!(bytes("512M"), 512 << 20);
+        assert_eq!(bytes("1.5GiB"), 3 << 29);
+        assert_eq!(bytes("2Ti"), 2 << 40);
+        assert_eq!(bytes("3g"), 3 << 30);
+        assert_eq!(bytes("1KB"), 1_000);
+        assert_eq!(bytes("1.5MB"), 1_500_000);
+        assert_eq!(bytes(" 2 GB "), 2_000_000_000);
+    }
+
+    #[test]
+    fn flags_bare_numbers_as_unitless() {
+        let size: Size = "100".parse().unwrap();
+        assert_eq!(size.bytes(), 100);
+        assert!(size.is_unitless());
+        assert!(!"100B".parse::<Size>().unwrap().is_unitless());
+
+        // Kept unrounded, since the caller scales it
+        let size: Size = "1.5".parse().unwrap();
+        assert_eq!(size.bytes(), 2);
+        assert_eq!(size.unitless_number(), Some(1.5));
+    }
+
+    #[test]
+    fn rejects_bad_input() {
+        assert!("".parse::<Size>().is_err());
+        assert!("M".parse::<Size>().is_err());
+        assert!("1.2.3M".parse::<Size>().is_err());
+        assert!("-1M".parse::<Size>().is_err());
+        assert!("12X".parse::<Size>().unwrap_err().contains("unknown unit 'X'"));
+        assert!("5PB".parse::<Size>().is_err());
+        assert!("5MiBs".parse::<Size>().is_err());
+    }
+
+    #[test]
+    fn displays_the_largest_exact_unit() {
+        assert_eq!(Size::from_bytes(512 << 20).to_string(), "512M");
+        assert_eq!(Size::from_bytes(1536).to_string(), "1536B");
+        assert_eq!(Size::from_bytes(3 << 30).to_string(), "3G");
+        assert_eq!(Size::from_bytes(0).to_string(), "0B");
+        assert_eq!(Size::from_bytes(1_000_000).to_string(), "1000000B");
+    }
+}
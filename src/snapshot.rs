@@ -0,0 +1,217 @@
+//! Snapshot types shared by the collectors, the UI and the exporters.
+
+use crate::cgroup::Container;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sysinfo::System;
+
+/// Resource usage of a single process at one refresh.
+///
+/// Serialized as a line of a recording, which leaves out what a replay works
+/// out from the snapshots (CPU usage, rates, the count and when it was read)
+/// and fields at their default, so kernel threads take little room.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UsageInfo {
+    /// Process ID, or the thread ID for thread rows
+    pub pid: u32,
+    /// Process the row belongs to: its own PID, or the owning process for thread rows
+    pub tgid: u32,
+    /// Parent process ID
+    pub ppid: u32,
+    /// Effective user ID of the owner
+    pub uid: u32,
+    /// Name of the owner, or the UID if it has no passwd entry
+    pub user: String,
+    /// Process name, or the thread name for thread rows
+    pub name: String,
+    /// Scheduling state as a `ps` letter: R, S, D, I, T, t, Z or X
+    pub state: char,
+    /// Whether this is a kernel thread or kernel task rather than a userland process
+    #[serde(skip_serializing_if = "is_default")]
+    pub kernel: bool,
+    /// Nice value, from -20 (highest priority) to 19
+    #[serde(skip_serializing_if = "is_default")]
+    pub nice: i32,
+    /// Scheduling priority as reported by the kernel
+    #[serde(skip_serializing_if = "is_default")]
+    pub priority: i32,
+    /// Number of threads
+    pub threads: u32,
+    /// Number of processes the row stands for: 1, or the size of a group
+    #[serde(skip)]
+    pub count: u32,
+    /// CPU usage in percent
+    #[serde(skip)]
+    pub cpu: f64,
+    /// CPU time consumed since the process started, in seconds
+    #[serde(skip_serializing_if = "is_default")]
+    pub cpu_time: f64,
+    /// Resident memory in bytes
+    #[serde(skip_serializing_if = "is_default")]
+    pub mem: u64,
+    /// Virtual memory size in bytes
+    #[serde(skip_serializing_if = "is_default")]
+    pub virt: u64,
+    /// Resident memory shared with other processes, in bytes
+    #[serde(skip_serializing_if = "is_default")]
+    pub shared: u64,
+    /// Proportional set size: resident memory with each shared page divided
+    /// among the processes mapping it, in bytes (Linux only, read on demand)
+    #[serde(skip_serializing_if = "is_default")]
+    pub pss: u64,
+    /// Unique set size: resident memory no other process maps, in bytes
+    /// (Linux only, read on demand)
+    #[serde(skip_serializing_if = "is_default")]
+    pub uss: u64,
+    /// Resident memory also mapped by other processes, in bytes (Linux only,
+    /// read on demand); unlike `shared` this includes anonymous memory
+    #[serde(skip_serializing_if = "is_default")]
+    pub mapped_shared: u64,
+    /// Memory swapped out, in bytes (Linux only, read on demand)
+    #[serde(skip_serializing_if = "is_default")]
+    pub swap: u64,
+    /// Start time in seconds since the epoch
+    pub start_time: u64,
+    /// When the row was read in seconds since the epoch, which `elapsed` counts up to
+    #[serde(skip)]
+    pub sampled_at: u64,
+    /// Full command line with arguments separated by spaces, empty for kernel threads
+    #[serde(skip_serializing_if = "is_default")]
+    pub cmdline: String,
+    /// Path of the executable, empty if it cannot be read
+    #[serde(skip_serializing_if = "is_default")]
+    pub exe: String,
+    /// Control group path, like `/system.slice/docker-<id>.scope` (Linux only)
+    #[serde(skip_serializing_if = "is_default")]
+    pub cgroup: String,
+    /// Container the process runs in, recognized from its control group
+    #[serde(skip_serializing_if = "is_default")]
+    pub container: Option<Container>,
+    /// Bytes read from storage since the process started
+    #[serde(skip_serializing_if = "is_default")]
+    pub read_bytes: u64,
+    /// Bytes written to storage since the process started
+    #[serde(skip_serializing_if = "is_default")]
+    pub write_bytes: u64,
+    /// Read system calls since the process started (Linux only)
+    #[serde(skip_serializing_if = "is_default")]
+    pub read_syscalls: u64,
+    /// Write system calls since the process started (Linux only)
+    #[serde(skip_serializing_if = "is_default")]
+    pub write_syscalls: u64,
+    /// Bytes read from storage per second
+    #[serde(skip)]
+    pub read_rate: f64,
+    /// Bytes written to storage per second
+    #[serde(skip)]
+    pub write_rate: f64,
+}
+
+fn is_default<T: Default + PartialEq>(value: &T) -> bool {
+    *value == T::default()
+}
+
+/// Number of processes in each scheduling state
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskCounts {
+    pub total: usize,
+    pub running: usize,
+    /// Sleeping, including uninterruptible (D) and idle (I) processes
+    pub sleeping: usize,
+    pub stopped: usize,
+    pub zombie: usize,
+}
+
+impl TaskCounts {
+    /// Count `processes` by their state
+    pub fn count(processes: &[UsageInfo]) -> Self {
+        let mut counts = Self {
+            total: processes.len(),
+            ..Self::default()
+        };
+
+        for process in processes {
+            match process.state {
+                'R' => counts.running += 1,
+                'S' | 'D' | 'I' => counts.sleeping += 1,
+                'T' | 't' => counts.stopped += 1,
+                'Z' => counts.zombie += 1,
+                _ => {}
+            }
+        }
+
+        counts
+    }
+}
+
+/// Total, used and available amount of a memory pool in bytes
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MemoryUsage {
+    pub total: u64,
+    pub used: u64,
+    pub available: u64,
+}
+
+/// Machine-wide figures shown above the process table
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SystemSummary {
+    /// Seconds since boot
+    pub uptime: u64,
+    /// 1, 5 and 15 minute load averages
+    pub load_average: [f64; 3],
+    pub tasks: TaskCounts,
+    /// Utilization of each core in percent
+    pub cpus: Vec<f32>,
+    pub memory: MemoryUsage,
+    pub swap: MemoryUsage,
+}
+
+impl SystemSummary {
+    /// Gather the summary from `sys`, which must have fresh CPU and memory data,
+    /// and from the unfiltered process list
+    pub fn collect(sys: &System, processes: &[UsageInfo]) -> Self {
+        let load = System::load_average();
+
+        Self {
+            uptime: System::uptime(),
+            load_average: [load.one, load.five, load.fifteen],
+            tasks: TaskCounts::count(processes),
+            cpus: sys.cpus().iter().map(|cpu| cpu.cpu_usage()).collect(),
+            memory: MemoryUsage {
+                total: sys.total_memory(),
+                used: sys.used_memory(),
+                available: sys.available_memory(),
+            },
+            swap: MemoryUsage {
+                total: sys.total_swap(),
+                used: sys.used_swap(),
+                available: sys.free_swap(),
+            },
+        }
+    }
+}
+
+/// Everything collected during one refresh
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Snapshot {
+    /// When the refresh finished
+    pub timestamp: DateTime<Utc>,
+    /// Host name of the machine the snapshot was taken on
+    pub host: String,
+    pub system: SystemSummary,
+    /// Processes after filtering and sorting
+    pub processes: Vec<UsageInfo>,
+}
+
+impl Snapshot {
+    /// Create a snapshot of `processes` stamped with the current time and host name
+    pub fn new(system: SystemSummary, processes: Vec<UsageInfo>) -> Self {
+        Self {
+            timestamp: Utc::now(),
+            host: System::host_name().unwrap_or_default(),
+            system,
+            processes,
+        }
+    }
+}
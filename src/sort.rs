@@ -0,0 +1,173 @@
+//! Ordering processes by one or more columns.
+
+use crate::columns::Column;
+use crate::snapshot::UsageInfo;
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+/// One column to sort by and its direction.
+///
+/// Written as `name[:asc|desc]`, e.g. `cpu:desc`. Without a direction,
+/// usage columns like cpu and mem sort descending and the others ascending.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SortKey {
+    pub column: Column,
+    pub descending: bool,
+}
+
+impl SortKey {
+    pub fn new(column: Column) -> Self {
+        Self {
+            column,
+            descending: column.default_descending(),
+        }
+    }
+
+    fn compare(&self, a: &UsageInfo, b: &UsageInfo) -> Ordering {
+        let ordering = self.column.compare(a, b);
+        if self.descending { ordering.reverse() } else { ordering }
+    }
+}
+
+impl FromStr for SortKey {
+    type Err = String;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let (name, direction) = match spec.trim().split_once(':') {
+            Some((name, direction)) => (name, Some(direction)),
+            None => (spec.trim(), None),
+        };
+        let column = Column::from_key(name).ok_or_else(|| {
+            let keys: Vec<&str> = Column::ALL.iter().map(|column| column.key()).collect();
+            format!("unknown sort column '{}', expected one of: {}", name, keys.join(", "))
+        })?;
+
+        let mut key = SortKey::new(column);
+        match direction.map(str::to_lowercase).as_deref() {
+            None => {}
+            Some("asc" | "a" | "+") => key.descending = false,
+            Some("desc" | "d" | "-") => key.descending = true,
+            Some(other) => {
+                return Err(format!("invalid sort direction '{}', expected asc or desc", other));
+            }
+        }
+
+        Ok(key)
+    }
+}
+
+impl fmt::Display for SortKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let direction = if self.descending { "desc" } else { "asc" };
+        write!(f, "{}:{}", self.column.key(), direction)
+    }
+}
+
+impl TryFrom<String> for SortKey {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        spec.parse()
+    }
+}
+
+impl From<SortKey> for String {
+    fn from(key: SortKey) -> Self {
+        key.to_string()
+    }
+}
+
+/// Sort `stats` by `keys`, in order of precedence.
+///
+/// Processes that compare equal on every key are ordered by PID, so rows
+/// with equal values keep their positions from one refresh to the next.
+pub fn sort_processes(stats: &mut [UsageInfo], keys: &[SortKey]) {
+    stats.sort_by(|a, b| {
+        keys.iter()
+            .map(|key| key.compare(a, b))
+            .find(|ordering| ordering.is_ne())
+            .unwrap_or_else(|| a.pid.cmp(&b.pid))
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(spec: &str) -> SortKey {
+        spec.parse().unwrap()
+    }
+
+    fn process(pid: u32, name: &str, cpu: f64) -> UsageInfo {
+        UsageInfo {
+            pid,
+            name: name.to_string(),
+            cpu,
+            ..UsageInfo::default()
+        }
+    }
+
+    fn pids(stats: &[UsageInfo]) -> Vec<u32> {
+        stats.iter().map(|stat| stat.pid).collect()
+    }
+
+    #[test]
+    fn parses_columns_and_directions() {
+        assert_eq!(key("cpu:asc"), SortKey { column: Column::Cpu, descending: false });
+        assert_eq!(key("pid:desc"), SortKey { column: Column::Pid, descending: true });
+        assert_eq!(key(" name:D "), SortKey { column: Column::Command, descending: true });
+        assert_eq!(key("mem:+"), SortKey { column: Column::Memory, descending: false });
+
+        // Usage sorts highest first by default, everything else lowest first
+        assert!(key("cpu").descending);
+        assert!(!key("pid").descending);
+        assert!(!key("user").descending);
+    }
+
+    #[test]
+    fn round_trips_through_text() {
+        for spec in ["cpu:desc", "pid:asc", "user:desc"] {
+            assert_eq!(key(spec).to_string(), spec);
+        }
+    }
+
+    #[test]
+    fn rejects_unknown_columns_and_directions() {
+        let error = "bogus".parse::<SortKey>().unwrap_err();
+        assert!(error.starts_with("unknown sort column 'bogus', expected one of: pid,"), "{}", error);
+        assert_eq!(
+            "cpu:sideways".parse::<SortKey>().unwrap_err(),
+            "invalid sort direction 'sideways', expected asc or desc"
+        );
+        assert!("cpu:".parse::<SortKey>().is_err());
+    }
+
+    #[test]
+    fn sorts_by_each_key_in_turn() {
+        let mut stats = vec![
+            process(1, "bash", 5.0),
+            process(2, "cargo", 50.0),
+            process(3, "bash", 20.0),
+        ];
+        sort_processes(&mut stats, &[key("name:asc"), key("cpu:desc")]);
+        assert_eq!(pids(&stats), [3, 1, 2]);
+
+        sort_processes(&mut stats, &[key("cpu")]);
+        assert_eq!(pids(&stats), [2, 3, 1]);
+    }
+
+    #[test]
+    fn breaks_ties_by_pid() {
+        let mut stats = vec![process(30, "a", 1.0), process(4, "b", 1.0), process(12, "c", 1.0)];
+        sort_processes(&mut stats, &[key("cpu:desc")]);
+        assert_eq!(pids(&stats), [4, 12, 30]);
+
+        // Whatever order they arrive in, equal rows end up in the same place
+        stats.reverse();
+        sort_processes(&mut stats, &[key("cpu:asc")]);
+        assert_eq!(pids(&stats), [4, 12, 30]);
+    }
+}
@@ -0,0 +1,423 @@
+//! Linux backend reading `/proc/[pid]/{stat,statm,status,io,cmdline,exe}`,
+//! and the same files under `/proc/[pid]/task/[tid]` for threads.
+//! Control groups come from `cgroup`, the memory breakdown from `smaps_rollup`,
+//! and details from `cwd`, `environ`, `limits`, `fd`, `cgroup` and `ns`.
+
+use super::ProcessSource;
+use crate::cgroup;
+use crate::details::{Limit, Namespace, OpenFile, ProcessDetails};
+use crate::snapshot::UsageInfo;
+use crate::users;
+use std::fs;
+
+/// `PF_KTHREAD` from the per-process flags in `/proc/[pid]/stat`
+const PF_KTHREAD: u64 = 0x0020_0000;
+
+pub struct LinuxSource {
+    /// Kernel clock ticks per second (`_SC_CLK_TCK`)
+    clock_ticks: f64,
+    /// Size of a memory page in bytes
+    page_size: u64,
+    /// System boot time in seconds since the epoch
+    boot_time: u64,
+}
+
+impl LinuxSource {
+    pub fn new() -> Self {
+        // SAFETY: sysconf has no preconditions and only reads system configuration
+        let (clock_ticks, page_size) =
+            unsafe { (libc::sysconf(libc::_SC_CLK_TCK), libc::sysconf(libc::_SC_PAGESIZE)) };
+
+        Self {
+            clock_ticks: if clock_ticks > 0 { clock_ticks as f64 } else { 100.0 },
+            page_size: if page_size > 0 { page_size as u64 } else { 4096 },
+            boot_time: read_boot_time().unwrap_or(0),
+        }
+    }
+
+    /// Read a process or thread from its directory under `/proc`; `id` is the
+    /// PID or TID and `tgid` the process it belongs to
+    fn read_task(&self, dir: &str, id: u32, tgid: u32) -> Option<UsageInfo> {
+        let stat = fs::read_to_string(format!("{}/stat", dir)).ok()?;
+        let statm = fs::read_to_string(format!("{}/statm", dir)).ok()?;
+        let status = fs::read_to_string(format!("{}/status", dir)).unwrap_or_default();
+
+        // Fields after the command start at field 3 (state), see proc(5)
+        let (comm, fields) = parse_stat(&stat)?;
+        let state = fields.first()?.chars().next()?;
+        let ppid: u32 = fields.get(1)?.parse().ok()?;
+        let flags: u64 = fields.get(6)?.parse().ok()?;
+        let utime: u64 = fields.get(11)?.parse().ok()?;
+        let stime: u64 = fields.get(12)?.parse().ok()?;
+        let priority: i32 = fields.get(15)?.parse().ok()?;
+        let nice: i32 = fields.get(16)?.parse().ok()?;
+        let threads: u32 = fields.get(17)?.parse().ok()?;
+        let start_ticks: u64 = fields.get(19)?.parse().ok()?;
+        let virt: u64 = fields.get(20)?.parse().ok()?;
+
+        let mut pages = statm.split_whitespace().skip(1);
+        let resident_pages: u64 = pages.next()?.parse().ok()?;
+        let shared_pages: u64 = pages.next()?.parse().ok()?;
+
+        let name = status_field(&status, "Name").unwrap_or(comm).to_string();
+        // The Uid line lists the real, effective, saved and filesystem UIDs
+        let uid: u32 = status_field(&status, "Uid")
+            .and_then(|uids| uids.split_whitespace().nth(1)?.parse().ok())
+            .unwrap_or(0);
+
+        // An empty cmdline is not a reliable signal since zombies have one too,
+        // and PID 2 is an ordinary process inside a PID namespace, so rely on the
+        // flag, which kthreadd and every kernel thread carry
+        let kernel = flags & PF_KTHREAD != 0;
+        let io = read_io(dir);
+        let cgroup = fs::read_to_string(format!("{}/cgroup", dir))
+            .map(|content| cgroup::primary_path(&content))
+            .unwrap_or_default();
+
+        Some(UsageInfo {
+            pid: id,
+            tgid,
+            ppid,
+            uid,
+            user: users::name_of(uid),
+            name,
+            state,
+            kernel,
+            nice,
+            priority,
+            threads,
+            count: 1,
+            cpu: 0.0,
+            cpu_time: (utime + stime) as f64 / self.clock_ticks,
+            mem: resident_pages * self.page_size,
+            virt,
+            shared: shared_pages * self.page_size,
+            // Read by `read_memory_breakdown` when needed
+            pss: 0,
+            uss: 0,
+            mapped_shared: 0,
+            swap: 0,
+            start_time: self.boot_time + (start_ticks as f64 / self.clock_ticks) as u64,
+            // Stamped by `Collector` when it measures the row
+            sampled_at: 0,
+            cmdline: read_cmdline(dir),
+            exe: read_link(&format!("{}/exe", dir)).unwrap_or_default(),
+            container: cgroup::container(&cgroup),
+            cgroup,
+            read_bytes: io.read_bytes,
+            write_bytes: io.write_bytes,
+            read_syscalls: io.syscr,
+            write_syscalls: io.syscw,
+            read_rate: 0.0,
+            write_rate: 0.0,
+        })
+    }
+}
+
+impl Default for LinuxSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read the `btime` line from `/proc/stat`
+fn read_boot_time() -> Option<u64> {
+    let content = fs::read_to_string("/proc/stat").ok()?;
+    content
+        .lines()
+        .find_map(|line| line.strip_prefix("btime"))
+        .and_then(|value| value.trim().parse().ok())
+}
+
+/// Split `/proc/[pid]/stat` into the command name and the fields after it.
+///
+/// The command is wrapped in parentheses and may itself contain spaces or
+/// parentheses, so everything up to the last `)` belongs to it.
+fn parse_stat(content: &str) -> Option<(&str, Vec<&str>)> {
+    let open = content.find('(')?;
+    let close = content.rfind(')')?;
+    let comm = &content[open + 1..close];
+    let fields = content[close + 1..].split_whitespace().collect();
+    Some((comm, fields))
+}
+
+/// Look up a `Key:\tvalue` line in `/proc/[pid]/status`
+fn status_field<'a>(status: &'a str, key: &str) -> Option<&'a str> {
+    status.lines().find_map(|line| {
+        line.strip_prefix(key)
+            .and_then(|rest| rest.strip_prefix(':'))
+            .map(str::trim)
+    })
+}
+
+/// Read `cmdline` in a process directory, whose arguments are separated by NUL bytes
+fn read_cmdline(dir: &str) -> String {
+    let Ok(raw) = fs::read(format!("{}/cmdline", dir)) else {
+        return String::new();
+    };
+
+    raw.split(|&byte| byte == 0)
+        .filter(|arg| !arg.is_empty())
+        .map(String::from_utf8_lossy)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Storage and system call counters from `/proc/[pid]/io`
+#[derive(Default)]
+struct IoCounters {
+    read_bytes: u64,
+    write_bytes: u64,
+    syscr: u64,
+    syscw: u64,
+}
+
+/// Read `io` in a process directory, which only the owner and root may read; others get zeros
+fn read_io(dir: &str) -> IoCounters {
+    let Ok(content) = fs::read_to_string(format!("{}/io", dir)) else {
+        return IoCounters::default();
+    };
+
+    let field = |key| status_field(&content, key).and_then(|value| value.parse().ok());
+    IoCounters {
+        read_bytes: field("read_bytes").unwrap_or(0),
+        write_bytes: field("write_bytes").unwrap_or(0),
+        syscr: field("syscr").unwrap_or(0),
+        syscw: field("syscw").unwrap_or(0),
+    }
+}
+
+/// Memory totals from `smaps_rollup` or `smaps`, in bytes
+#[derive(Default)]
+struct SmapsTotals {
+    pss: u64,
+    private: u64,
+    shared: u64,
+    swap: u64,
+}
+
+/// Sum the `Key:   123 kB` lines of `smaps_rollup`, which has one of each,
+/// or of `smaps`, which has one per mapping
+fn parse_smaps(content: &str) -> SmapsTotals {
+    let mut totals = SmapsTotals::default();
+    for line in content.lines() {
+        let Some((key, value)) = line.split_once(':') else {
+            continue;
+        };
+        let Some(kilobytes) = value.split_whitespace().next().and_then(|kb| kb.parse::<u64>().ok())
+        else {
+            continue;
+        };
+
+        let bytes = kilobytes * 1024;
+        match key {
+            "Pss" => totals.pss += bytes,
+            "Private_Clean" | "Private_Dirty" => totals.private += bytes,
+            "Shared_Clean" | "Shared_Dirty" => totals.shared += bytes,
+            "Swap" => totals.swap += bytes,
+            _ => {}
+        }
+    }
+    totals
+}
+
+/// Target of a symbolic link such as `cwd` or an entry of `fd`
+fn read_link(path: &str) -> Option<String> {
+    fs::read_link(path)
+        .ok()
+        .map(|target| target.to_string_lossy().into_owned())
+}
+
+/// Read `environ` in a process directory, whose variables are separated by NUL bytes
+fn read_environment(dir: &str) -> Option<Vec<String>> {
+    let raw = fs::read(format!("{}/environ", dir)).ok()?;
+    Some(
+        raw.split(|&byte| byte == 0)
+            .filter(|variable| !variable.is_empty())
+            .map(|variable| String::from_utf8_lossy(variable).into_owned())
+            .collect(),
+    )
+}
+
+/// Read `limits` in a process directory.
+///
+/// It is a table aligned with spaces whose limit names contain spaces too, so
+/// the columns are cut at the positions of the headings.
+fn read_limits(dir: &str) -> Option<Vec<Limit>> {
+    let content = fs::read_to_string(format!("{}/limits", dir)).ok()?;
+    let mut lines = content.lines();
+    let header = lines.next()?;
+    let soft_at = header.find("Soft Limit")?;
+    let hard_at = header.find("Hard Limit")?;
+    let units_at = header.find("Units")?;
+
+    let column = |line: &str, start: usize, end: usize| {
+        line.get(start..end.min(line.len()))
+            .unwrap_or_default()
+            .trim()
+            .to_string()
+    };
+    Some(
+        lines
+            .map(|line| Limit {
+                name: column(line, 0, soft_at),
+                soft: column(line, soft_at, hard_at),
+                hard: column(line, hard_at, units_at),
+                units: column(line, units_at, line.len()),
+            })
+            .collect(),
+    )
+}
+
+/// List the `fd` directory of a process with the target of every descriptor
+fn read_files(dir: &str) -> Option<Vec<OpenFile>> {
+    let fd_dir = format!("{}/fd", dir);
+    let mut files: Vec<OpenFile> = fs::read_dir(&fd_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+        .map(|fd| OpenFile {
+            fd,
+            // The descriptor may be closed between listing and reading it
+            target: read_link(&format!("{}/{}", fd_dir, fd)).unwrap_or_default(),
+        })
+        .collect();
+    files.sort_by_key(|file| file.fd);
+    Some(files)
+}
+
+/// List the `ns` directory of a process, whose links name each namespace
+fn read_namespaces(dir: &str) -> Option<Vec<Namespace>> {
+    let ns_dir = format!("{}/ns", dir);
+    let mut namespaces: Vec<Namespace> = fs::read_dir(&ns_dir)
+        .ok()?
+        .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+        .filter_map(|kind| {
+            let id = read_link(&format!("{}/{}", ns_dir, kind))?;
+            Some(Namespace { kind, id })
+        })
+        .collect();
+    namespaces.sort_by(|a, b| a.kind.cmp(&b.kind));
+    Some(namespaces)
+}
+
+impl ProcessSource for LinuxSource {
+    fn pids(&self) -> Vec<u32> {
+        let Ok(entries) = fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .collect()
+    }
+
+    fn read(&self, pid: u32) -> Option<UsageInfo> {
+        self.read_task(&format!("/proc/{}", pid), pid, pid)
+    }
+
+    fn read_threads(&self, pid: u32) -> Vec<UsageInfo> {
+        let dir = format!("/proc/{}/task", pid);
+        let Ok(entries) = fs::read_dir(&dir) else {
+            return Vec::new();
+        };
+
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse().ok())
+            .filter_map(|tid| self.read_task(&format!("{}/{}", dir, tid), tid, pid))
+            .collect()
+    }
+
+    fn read_memory_breakdown(&self, process: &mut UsageInfo) {
+        // Threads share the memory of their process
+        let dir = format!("/proc/{}", process.tgid);
+        // smaps_rollup is new in Linux 4.14, smaps has the same fields per mapping;
+        // both are only readable by the owner and root
+        let Ok(content) = fs::read_to_string(format!("{}/smaps_rollup", dir))
+            .or_else(|_| fs::read_to_string(format!("{}/smaps", dir)))
+        else {
+            return;
+        };
+
+        let totals = parse_smaps(&content);
+        process.pss = totals.pss;
+        process.uss = totals.private;
+        process.mapped_shared = totals.shared;
+        process.swap = totals.swap;
+    }
+
+    fn details(&self, pid: u32) -> ProcessDetails {
+        let dir = format!("/proc/{}", pid);
+        ProcessDetails {
+            cwd: read_link(&format!("{}/cwd", dir)),
+            environment: read_environment(&dir),
+            limits: read_limits(&dir),
+            files: read_files(&dir),
+            cgroups: fs::read_to_string(format!("{}/cgroup", dir))
+                .ok()
+                .map(|content| content.lines().map(String::from).collect()),
+            namespaces: read_namespaces(&dir),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_smaps_rollup() {
+        let rollup = "\
+55d4c8a3e000-7ffd1b5fe000 ---p 00000000 00:00 0                          [rollup]
+Rss:               11520 kB
+Pss:                4263 kB
+Pss_Anon:           2048 kB
+Pss_File:           2215 kB
+Pss_Shmem:             0 kB
+Shared_Clean:       7168 kB
+Shared_Dirty:        256 kB
+Private_Clean:      1024 kB
+Private_Dirty:      3072 kB
+Referenced:        11520 kB
+Anonymous:          3328 kB
+Swap:                512 kB
+SwapPss:             512 kB
+Locked:                0 kB
+";
+        let totals = parse_smaps(rollup);
+        assert_eq!(totals.pss, 4263 * 1024);
+        assert_eq!(totals.private, 4096 * 1024);
+        assert_eq!(totals.shared, 7424 * 1024);
+        assert_eq!(totals.swap, 512 * 1024);
+    }
+
+    #[test]
+    fn sums_the_mappings_of_smaps() {
+        let smaps = "\
+5581b2a00000-5581b2a28000 r--p 00000000 08:01 1835015                    /usr/bin/cat
+Size:                160 kB
+Pss:                  40 kB
+Shared_Clean:        160 kB
+Private_Dirty:         0 kB
+Swap:                  0 kB
+VmFlags: rd mr mw me dw sd
+7f0e4c000000-7f0e4c021000 rw-p 00000000 00:00 0
+Size:                132 kB
+Pss:                  12 kB
+Shared_Clean:          0 kB
+Private_Dirty:        12 kB
+Swap:                  8 kB
+VmFlags: rd wr mr mw me nr sd
+";
+        let totals = parse_smaps(smaps);
+        assert_eq!(totals.pss, 52 * 1024);
+        assert_eq!(totals.private, 12 * 1024);
+        assert_eq!(totals.shared, 160 * 1024);
+        assert_eq!(totals.swap, 8 * 1024);
+    }
+
+    #[test]
+    fn reads_nothing_from_an_unreadable_smaps() {
+        let totals = parse_smaps("");
+        assert_eq!((totals.pss, totals.private, totals.shared, totals.swap), (0, 0, 0, 0));
+    }
+}
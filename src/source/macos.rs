@@ -0,0 +1,365 @@
+//! macOS backend built on libproc.
+
+use super::ProcessSource;
+use crate::details::{OpenFile, ProcessDetails};
+use crate::snapshot::UsageInfo;
+use crate::users;
+use libproc::libproc::file_info::{ListFDs, ProcFDType};
+use libproc::libproc::pid_rusage::{RUsageInfoV2, pidrusage};
+use libproc::libproc::proc_pid::{ListThreads, listpidinfo, name, pidinfo, pidpath};
+use libproc::libproc::bsd_info::BSDInfo;
+use libproc::libproc::task_info::TaskInfo;
+use libproc::libproc::thread_info::ThreadInfo;
+use libproc::processes;
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::ptr;
+
+/// `PROC_FLAG_SYSTEM` from `pbi_flags`, set for system processes such as kernel_task
+const PROC_FLAG_SYSTEM: u32 = 0x1;
+
+pub struct MacSource {
+    /// Nanoseconds per Mach absolute time unit, the unit of the rusage CPU times
+    nanos_per_tick: f64,
+    /// Size of the buffer `KERN_PROCARGS2` needs, from `KERN_ARGMAX`
+    arg_max: usize,
+    /// Command lines by PID and start time, since reading them takes a large
+    /// sysctl per process; pruned to the running processes by `pids`
+    cmdlines: RefCell<HashMap<(u32, u64), String>>,
+}
+
+impl MacSource {
+    pub fn new() -> Self {
+        let mut timebase = libc::mach_timebase_info { numer: 0, denom: 0 };
+        // SAFETY: the kernel only writes into the struct passed in.
+        // Deprecated in libc in favour of the mach2 crate, but unchanged
+        #[allow(deprecated)]
+        let result = unsafe { libc::mach_timebase_info(&mut timebase) };
+
+        // Intel Macs count in nanoseconds (1/1), Apple silicon in 125/3 ns ticks
+        let nanos_per_tick = if result == 0 && timebase.denom != 0 {
+            timebase.numer as f64 / timebase.denom as f64
+        } else {
+            1.0
+        };
+        Self {
+            nanos_per_tick,
+            arg_max: read_arg_max().unwrap_or(0),
+            cmdlines: RefCell::new(HashMap::new()),
+        }
+    }
+
+    /// Command line of `pid`, read once per process and then taken from the
+    /// cache; a process that rewrites its arguments later keeps the original
+    fn cmdline(&self, pid: u32, start_time: u64) -> String {
+        self.cmdlines
+            .borrow_mut()
+            .entry((pid, start_time))
+            .or_insert_with(|| {
+                read_procargs(pid, self.arg_max)
+                    .and_then(|buffer| split_procargs(&buffer))
+                    .map(|(args, _)| args.join(" "))
+                    .unwrap_or_default()
+            })
+            .clone()
+    }
+}
+
+impl Default for MacSource {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ProcessSource for MacSource {
+    fn pids(&self) -> Vec<u32> {
+        let pids = processes::pids_by_type(processes::ProcFilter::All).unwrap_or_default();
+        let running: HashSet<u32> = pids.iter().copied().collect();
+        self.cmdlines
+            .borrow_mut()
+            .retain(|(pid, _), _| running.contains(pid));
+        pids
+    }
+
+    fn read(&self, pid: u32) -> Option<UsageInfo> {
+        let usage = pidrusage::<RUsageInfoV2>(pid as i32).ok()?;
+        let proc_name = name(pid as i32).unwrap_or_else(|_| "Unknown".to_string());
+        // BSD info is readable for every process, task info only for our own
+        // unless running as root, so they are fetched separately
+        let bsd_info = pidinfo::<BSDInfo>(pid as i32, 0).ok();
+        let bsd_info = bsd_info.as_ref();
+        let start_time = bsd_info.map_or(0, |info| info.pbi_start_tvsec);
+        let ppid = bsd_info.map_or(0, |info| info.pbi_ppid);
+        let uid = bsd_info.map_or(0, |info| info.pbi_uid);
+        let state = bsd_info.map_or('?', |info| state_letter(info.pbi_status));
+        let nice = bsd_info.map_or(0, |info| info.pbi_nice);
+        // kernel_task is PID 0 and the only process that runs in the kernel
+        let kernel = pid == 0 || bsd_info.is_some_and(|info| info.pbi_flags & PROC_FLAG_SYSTEM != 0);
+
+        let task = pidinfo::<TaskInfo>(pid as i32, 0).ok();
+        let task = task.as_ref();
+        let threads = task.map_or(0, |task| task.pti_threadnum as u32);
+        let priority = task.map_or(0, |task| task.pti_priority);
+        let virt = task.map_or(0, |task| task.pti_virtual_size);
+
+        Some(UsageInfo {
+            pid,
+            tgid: pid,
+            ppid,
+            uid,
+            user: users::name_of(uid),
+            name: proc_name,
+            state,
+            kernel,
+            nice,
+            priority,
+            threads,
+            count: 1,
+            cpu: 0.0,
+            cpu_time: (usage.ri_system_time + usage.ri_user_time) as f64 * self.nanos_per_tick
+                / 1_000_000_000.0,
+            mem: usage.ri_resident_size,
+            virt,
+            // Mach does not report shared resident memory per process
+            shared: 0,
+            pss: 0,
+            uss: 0,
+            mapped_shared: 0,
+            swap: 0,
+            start_time,
+            // Stamped by `Collector` when it measures the row
+            sampled_at: 0,
+            cmdline: self.cmdline(pid, start_time),
+            exe: pidpath(pid as i32).unwrap_or_default(),
+            // Containers on macOS run inside a Linux VM, invisible from the host
+            cgroup: String::new(),
+            container: None,
+            read_bytes: usage.ri_diskio_bytesread,
+            write_bytes: usage.ri_diskio_byteswritten,
+            // Mach only counts system calls as a whole, not reads and writes
+            read_syscalls: 0,
+            write_syscalls: 0,
+            read_rate: 0.0,
+            write_rate: 0.0,
+        })
+    }
+
+    fn read_threads(&self, pid: u32) -> Vec<UsageInfo> {
+        let Some(process) = self.read(pid) else {
+            return Vec::new();
+        };
+        // Listing needs an upper bound on the number of threads
+        let count = pidinfo::<TaskInfo>(pid as i32, 0).map_or(0, |task| task.pti_threadnum as usize);
+        let Ok(handles) = listpidinfo::<ListThreads>(pid as i32, count) else {
+            return Vec::new();
+        };
+
+        handles
+            .into_iter()
+            .filter_map(|handle| self.read_thread(&process, handle))
+            .collect()
+    }
+
+    fn read_memory_breakdown(&self, _process: &mut UsageInfo) {
+        // Mach has no per-process account of shared and swapped pages to break
+        // resident memory down by, so the breakdown stays at zero
+    }
+
+    fn details(&self, pid: u32) -> ProcessDetails {
+        ProcessDetails {
+            cwd: read_cwd(pid),
+            environment: read_procargs(pid, self.arg_max)
+                .and_then(|buffer| split_procargs(&buffer))
+                .map(|(_, env)| env),
+            // Resource limits of other processes can't be read on macOS,
+            // and control groups and namespaces are Linux concepts
+            limits: None,
+            files: read_files(pid),
+            cgroups: None,
+            namespaces: None,
+        }
+    }
+}
+
+impl MacSource {
+    /// Read one thread of `process`, identified by its handle from `PROC_PIDLISTTHREADS`
+    fn read_thread(&self, process: &UsageInfo, handle: u64) -> Option<UsageInfo> {
+        let info = pidinfo::<ThreadInfo>(process.pid as i32, handle).ok()?;
+        let name: String = info
+            .pth_name
+            .iter()
+            .take_while(|&&c| c != 0)
+            .map(|&c| c as u8 as char)
+            .collect();
+
+        Some(UsageInfo {
+            // Handles are unique within the process, which is all rows are keyed on
+            // together with `tgid`, so the low 32 bits serve as the thread ID
+            pid: handle as u32,
+            tgid: process.pid,
+            name: if name.is_empty() { process.name.clone() } else { name },
+            state: thread_state_letter(info.pth_run_state),
+            priority: info.pth_curpri,
+            threads: 1,
+            // Unlike rusage, thread times are always in nanoseconds
+            cpu_time: (info.pth_user_time + info.pth_system_time) as f64 / 1_000_000_000.0,
+            // Storage I/O is only counted per process
+            read_bytes: 0,
+            write_bytes: 0,
+            ..process.clone()
+        })
+    }
+}
+
+/// Map a Mach thread run state (TH_STATE_*) to a `ps` letter
+fn thread_state_letter(state: i32) -> char {
+    match state {
+        1 => 'R',
+        2 => 'T',
+        3 => 'S',
+        4 => 'D',
+        5 => 'Z',
+        _ => '?',
+    }
+}
+
+/// Current working directory of a process from `PROC_PIDVNODEPATHINFO`
+fn read_cwd(pid: u32) -> Option<String> {
+    // SAFETY: the struct is plain data for which all zeros is a valid value
+    let mut info: libc::proc_vnodepathinfo = unsafe { std::mem::zeroed() };
+    let size = std::mem::size_of::<libc::proc_vnodepathinfo>() as libc::c_int;
+    // SAFETY: `size` is the size of `info`, which the kernel writes into
+    let written = unsafe {
+        libc::proc_pidinfo(
+            pid as libc::c_int,
+            libc::PROC_PIDVNODEPATHINFO,
+            0,
+            (&mut info as *mut libc::proc_vnodepathinfo).cast(),
+            size,
+        )
+    };
+    if written != size {
+        return None;
+    }
+
+    // libc splits the MAXPATHLEN path buffer into chunks
+    let path: Vec<u8> = info
+        .pvi_cdir
+        .vip_path
+        .iter()
+        .flatten()
+        .take_while(|&&c| c != 0)
+        .map(|&c| c as u8)
+        .collect();
+    Some(String::from_utf8_lossy(&path).into_owned())
+}
+
+/// Open file descriptors of a process with their type; libproc has no call for
+/// the path behind a descriptor, so files are only described by their type
+fn read_files(pid: u32) -> Option<Vec<OpenFile>> {
+    let count = pidinfo::<BSDInfo>(pid as i32, 0).ok()?.pbi_nfiles as usize;
+    let mut files: Vec<OpenFile> = listpidinfo::<ListFDs>(pid as i32, count)
+        .ok()?
+        .into_iter()
+        .map(|info| OpenFile {
+            fd: info.proc_fd as u32,
+            target: match ProcFDType::from(info.proc_fdtype) {
+                ProcFDType::VNode => "file",
+                ProcFDType::Socket => "socket",
+                ProcFDType::Pipe => "pipe",
+                ProcFDType::KQueue => "kqueue",
+                ProcFDType::PSHM => "shared memory",
+                ProcFDType::PSEM => "semaphore",
+                ProcFDType::FSEvents => "fsevents",
+                _ => "unknown",
+            }
+            .to_string(),
+        })
+        .collect();
+    files.sort_by_key(|file| file.fd);
+    Some(files)
+}
+
+/// Map a BSD `p_stat` value (SIDL, SRUN, SSLEEP, SSTOP, SZOMB) to a `ps` letter
+fn state_letter(status: u32) -> char {
+    match status {
+        1 => 'I',
+        2 => 'R',
+        3 => 'S',
+        4 => 'T',
+        5 => 'Z',
+        _ => '?',
+    }
+}
+
+/// Largest size of the arguments and environment of a process (`KERN_ARGMAX`)
+fn read_arg_max() -> Option<usize> {
+    let mut mib = [libc::CTL_KERN, libc::KERN_ARGMAX];
+    let mut arg_max: libc::c_int = 0;
+    let mut size = std::mem::size_of::<libc::c_int>();
+    // SAFETY: the output buffer is a c_int and `size` holds its length
+    let result = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            2,
+            (&mut arg_max as *mut libc::c_int).cast(),
+            &mut size,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if result != 0 || arg_max <= 0 {
+        return None;
+    }
+    Some(arg_max as usize)
+}
+
+/// Read the raw `KERN_PROCARGS2` buffer of a process into a buffer of `arg_max` bytes.
+///
+/// It starts with `argc` as a C int, followed by the executable path, padding
+/// NULs, the NUL-terminated arguments and then the environment.
+fn read_procargs(pid: u32, arg_max: usize) -> Option<Vec<u8>> {
+    if arg_max == 0 {
+        return None;
+    }
+
+    let mut mib = [libc::CTL_KERN, libc::KERN_PROCARGS2, pid as libc::c_int];
+    let mut buffer = vec![0u8; arg_max];
+    let mut size = buffer.len();
+    // SAFETY: `size` holds the length of `buffer`, which the kernel writes into
+    let result = unsafe {
+        libc::sysctl(
+            mib.as_mut_ptr(),
+            3,
+            buffer.as_mut_ptr().cast(),
+            &mut size,
+            ptr::null_mut(),
+            0,
+        )
+    };
+    if result != 0 {
+        return None;
+    }
+
+    buffer.truncate(size);
+    Some(buffer)
+}
+
+/// Split a `KERN_PROCARGS2` buffer into the arguments and the environment
+fn split_procargs(buffer: &[u8]) -> Option<(Vec<String>, Vec<String>)> {
+    let argc_len = std::mem::size_of::<libc::c_int>();
+    let argc = libc::c_int::from_ne_bytes(buffer.get(..argc_len)?.try_into().ok()?) as usize;
+
+    // Skip the executable path and the NULs padding it
+    let rest = &buffer[argc_len..];
+    let path_end = rest.iter().position(|&byte| byte == 0)?;
+    let strings_start = rest[path_end..].iter().position(|&byte| byte != 0)? + path_end;
+
+    let mut strings = rest[strings_start..]
+        .split(|&byte| byte == 0)
+        .map(|string| String::from_utf8_lossy(string).into_owned());
+    let args: Vec<String> = strings.by_ref().take(argc).collect();
+    let env = strings.take_while(|string| !string.is_empty()).collect();
+
+    Some((args, env))
+}
@@ -0,0 +1,51 @@
+//! Platform backends that enumerate processes and read their resource usage.
+
+use crate::details::ProcessDetails;
+use crate::snapshot::UsageInfo;
+
+#[cfg(target_os = "linux")]
+mod linux;
+#[cfg(target_os = "macos")]
+mod macos;
+
+#[cfg(target_os = "linux")]
+pub use linux::LinuxSource;
+#[cfg(target_os = "macos")]
+pub use macos::MacSource;
+
+/// A source of per-process resource usage.
+///
+/// `read` fills in cumulative counters such as `UsageInfo::cpu_time` and
+/// `read_bytes` and leaves `cpu` and the I/O rates at zero; turning two
+/// readings into rates is left to `Collector`.
+pub trait ProcessSource {
+    /// List the PIDs of all processes currently running
+    fn pids(&self) -> Vec<u32>;
+
+    /// Read a single process, or `None` if it has exited or is not accessible
+    fn read(&self, pid: u32) -> Option<UsageInfo>;
+
+    /// Read every thread of a process as a row of its own, with `pid` set to
+    /// the thread ID and `tgid` to the process; empty if it is not accessible
+    fn read_threads(&self, pid: u32) -> Vec<UsageInfo>;
+
+    /// Fill in the memory breakdown of a process read with `read` or
+    /// `read_threads`: PSS, USS, shared and swapped memory. It costs more than
+    /// the rest of a reading, so `Collector` only asks for it when needed.
+    fn read_memory_breakdown(&self, process: &mut UsageInfo);
+
+    /// Read what the detail pane shows about a process beyond its `UsageInfo`
+    fn details(&self, pid: u32) -> ProcessDetails;
+}
+
+/// Create the process source for the platform rustop was compiled for
+#[cfg(target_os = "linux")]
+pub fn default_source() -> Box<dyn ProcessSource> {
+    Box::new(LinuxSource::new())
+}
+
+/// Create the process source for the platform rustop was compiled for
+#[cfg(target_os = "macos")]
+pub fn default_source() -> Box<dyn ProcessSource> {
+    Box::new(MacSource::new())
+}
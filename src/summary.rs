@@ -0,0 +1,97 @@
+//! Rendering the top-style system summary shown above the process table.
+
+use crate::format::{FormatOptions, format_size};
+use crate::snapshot::SystemSummary;
+
+/// Width of a per-core utilization bar including its label and percentage
+const CORE_BAR_WIDTH: usize = 32;
+
+/// Format the summary as lines of text fitting into `width` columns; `threads`
+/// says whether the task counts are of threads rather than processes
+pub fn lines(
+    summary: &SystemSummary,
+    width: usize,
+    options: &FormatOptions,
+    threads: bool,
+) -> Vec<String> {
+    // Totals always carry their unit, picked per value unless one is fixed
+    let options = FormatOptions {
+        human_readable: true,
+        ..*options
+    };
+
+    let [one, five, fifteen] = summary.load_average;
+    let tasks = &summary.tasks;
+
+    let mut lines = vec![
+        format!(
+            "up {}, load average: {:.2}, {:.2}, {:.2}",
+            format_uptime(summary.uptime),
+            one,
+            five,
+            fifteen
+        ),
+        format!(
+            "{}: {} total, {} running, {} sleeping, {} stopped, {} zombie",
+            if threads { "Threads" } else { "Tasks" },
+            tasks.total,
+            tasks.running, tasks.sleeping, tasks.stopped, tasks.zombie
+        ),
+    ];
+
+    let bars: Vec<String> = summary
+        .cpus
+        .iter()
+        .enumerate()
+        .map(|(index, usage)| core_bar(index, *usage))
+        .collect();
+    let per_line = (width / (CORE_BAR_WIDTH + 1)).max(1);
+    for chunk in bars.chunks(per_line) {
+        lines.push(chunk.join(" "));
+    }
+
+    lines.push(format!(
+        "Mem:  {} total, {} used, {} available",
+        format_size(summary.memory.total, &options),
+        format_size(summary.memory.used, &options),
+        format_size(summary.memory.available, &options),
+    ));
+    lines.push(format!(
+        "Swap: {} total, {} used, {} free",
+        format_size(summary.swap.total, &options),
+        format_size(summary.swap.used, &options),
+        format_size(summary.swap.available, &options),
+    ));
+
+    lines
+}
+
+/// Draw a bar like `CPU0 [||||||        35.2%]`
+fn core_bar(index: usize, usage: f32) -> String {
+    let label = format!("CPU{:<2}", index);
+    let percent = format!("{:5.1}%", usage);
+    // Label, space, brackets and percentage take up the rest of the width
+    let inner = CORE_BAR_WIDTH - label.len() - 3 - percent.len();
+    let filled = ((usage.clamp(0.0, 100.0) / 100.0) * inner as f32).round() as usize;
+
+    format!(
+        "{} [{}{}{}]",
+        label,
+        "|".repeat(filled),
+        " ".repeat(inner - filled),
+        percent
+    )
+}
+
+/// Format seconds since boot like `3 days, 04:12`
+fn format_uptime(seconds: u64) -> String {
+    let days = seconds / 86_400;
+    let hours = (seconds % 86_400) / 3_600;
+    let minutes = (seconds % 3_600) / 60;
+
+    match days {
+        0 => format!("{:02}:{:02}", hours, minutes),
+        1 => format!("1 day, {:02}:{:02}", hours, minutes),
+        _ => format!("{} days, {:02}:{:02}", days, hours, minutes),
+    }
+}
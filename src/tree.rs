@@ -0,0 +1,142 @@
+//! Arranging processes into a parent/child hierarchy for the tree view.
+
+use crate::snapshot::UsageInfo;
+use std::collections::{HashMap, HashSet};
+
+/// A process as it appears in the table, with the tree glyphs drawn before its name
+#[derive(Debug, Clone)]
+pub struct DisplayRow {
+    pub info: UsageInfo,
+    /// Indentation and branch glyphs, empty in the flat view
+    pub prefix: String,
+}
+
+impl DisplayRow {
+    /// Name shown in the COMMAND column
+    pub fn label(&self) -> String {
+        format!("{}{}", self.prefix, self.info.name)
+    }
+}
+
+/// Rows for the flat view, in the order given
+pub fn flat(stats: Vec<UsageInfo>) -> Vec<DisplayRow> {
+    stats
+        .into_iter()
+        .map(|info| DisplayRow {
+            info,
+            prefix: String::new(),
+        })
+        .collect()
+}
+
+/// Arrange `stats` into a tree, keeping their order among siblings.
+///
+/// Processes whose parent is not in `stats` (because it was filtered out or
+/// has exited) become roots. Children of PIDs in `collapsed` are hidden. With
+/// `rollup`, each row's CPU and memory are summed over its whole subtree.
+pub fn build(stats: Vec<UsageInfo>, collapsed: &HashSet<u32>, rollup: bool) -> Vec<DisplayRow> {
+    let present: HashSet<u32> = stats.iter().map(|stat| stat.pid).collect();
+    let mut children: HashMap<u32, Vec<usize>> = HashMap::new();
+    let mut roots = Vec::new();
+
+    for (index, stat) in stats.iter().enumerate() {
+        if stat.ppid != stat.pid && present.contains(&stat.ppid) {
+            children.entry(stat.ppid).or_default().push(index);
+        } else {
+            roots.push(index);
+        }
+    }
+
+    let mut totals = HashMap::new();
+    if rollup {
+        for &root in &roots {
+            subtree_totals(root, &stats, &children, &mut totals);
+        }
+    }
+
+    let mut rows = Vec::with_capacity(stats.len());
+    let mut tree = Tree {
+        stats: &stats,
+        children: &children,
+        collapsed,
+        totals: &totals,
+        rows: &mut rows,
+    };
+    for &root in &roots {
+        tree.walk(root, String::new(), None);
+    }
+
+    rows
+}
+
+/// Sum CPU and memory over the subtree rooted at `index`
+fn subtree_totals(
+    index: usize,
+    stats: &[UsageInfo],
+    children: &HashMap<u32, Vec<usize>>,
+    totals: &mut HashMap<usize, (f64, u64)>,
+) -> (f64, u64) {
+    let stat = &stats[index];
+    let mut total = (stat.cpu, stat.mem);
+
+    for &child in children.get(&stat.pid).into_iter().flatten() {
+        let (cpu, mem) = subtree_totals(child, stats, children, totals);
+        total.0 += cpu;
+        total.1 += mem;
+    }
+
+    totals.insert(index, total);
+    total
+}
+
+struct Tree<'a> {
+    stats: &'a [UsageInfo],
+    children: &'a HashMap<u32, Vec<usize>>,
+    collapsed: &'a HashSet<u32>,
+    totals: &'a HashMap<usize, (f64, u64)>,
+    rows: &'a mut Vec<DisplayRow>,
+}
+
+impl Tree<'_> {
+    /// Emit the row at `index` and its visible descendants.
+    ///
+    /// `indent` holds the glyphs of the ancestors' columns; `is_last` is
+    /// `None` for roots and otherwise tells whether this is the last sibling.
+    fn walk(&mut self, index: usize, indent: String, is_last: Option<bool>) {
+        let mut info = self.stats[index].clone();
+        if let Some(&(cpu, mem)) = self.totals.get(&index) {
+            info.cpu = cpu;
+            info.mem = mem;
+        }
+
+        let kids = self.children.get(&info.pid).map(Vec::as_slice).unwrap_or(&[]);
+        let is_collapsed = !kids.is_empty() && self.collapsed.contains(&info.pid);
+
+        let branch = match is_last {
+            None => "",
+            Some(false) => "├─ ",
+            Some(true) => "└─ ",
+        };
+        let marker = if is_collapsed { "[+] " } else { "" };
+        let pid = info.pid;
+
+        self.rows.push(DisplayRow {
+            info,
+            prefix: format!("{}{}{}", indent, branch, marker),
+        });
+
+        if is_collapsed {
+            return;
+        }
+
+        let child_indent = match is_last {
+            None => indent,
+            Some(false) => format!("{}│  ", indent),
+            Some(true) => format!("{}   ", indent),
+        };
+        let kids = self.children.get(&pid).map(Vec::as_slice).unwrap_or(&[]);
+        for (position, &child) in kids.iter().enumerate() {
+            self.walk(child, child_indent.clone(), Some(position + 1 == kids.len()));
+        }
+    }
+}
@@ -0,0 +1,134 @@
+//! Resolving user names and UIDs through the passwd database.
+
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::sync::{Mutex, OnceLock};
+
+/// Names of UIDs looked up so far; `None` marks UIDs without a passwd entry
+static NAMES: OnceLock<Mutex<HashMap<u32, Option<String>>>> = OnceLock::new();
+
+/// Name of the user with `uid`, or the UID itself if it has no passwd entry
+pub fn name_of(uid: u32) -> String {
+    let mut names = NAMES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+
+    names
+        .entry(uid)
+        .or_insert_with(|| lookup_name(uid))
+        .clone()
+        .unwrap_or_else(|| uid.to_string())
+}
+
+/// Call a reentrant passwd lookup, growing the string buffer until it fits
+fn with_passwd<T>(
+    lookup: impl Fn(&mut libc::passwd, &mut [libc::c_char], &mut *mut libc::passwd) -> libc::c_int,
+    extract: impl Fn(&libc::passwd) -> T,
+) -> Option<T> {
+    let mut buffer = vec![0 as libc::c_char; 1024];
+
+    loop {
+        // SAFETY: passwd is a plain C struct for which all zeroes is a valid value
+        let mut entry: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut result = std::ptr::null_mut();
+
+        match lookup(&mut entry, &mut buffer, &mut result) {
+            libc::ERANGE if buffer.len() < 1 << 20 => buffer.resize(buffer.len() * 2, 0),
+            0 if !result.is_null() => return Some(extract(&entry)),
+            _ => return None,
+        }
+    }
+}
+
+fn lookup_name(uid: u32) -> Option<String> {
+    with_passwd(
+        // SAFETY: all pointers are valid for the duration of the call and the
+        // buffer length matches the buffer
+        |entry, buffer, result| unsafe {
+            libc::getpwuid_r(uid, entry, buffer.as_mut_ptr(), buffer.len(), result)
+        },
+        // SAFETY: on success pw_name points to a NUL-terminated string in the buffer
+        |entry| unsafe { CStr::from_ptr(entry.pw_name) }.to_string_lossy().into_owned(),
+    )
+}
+
+/// UID of the user called `name`, if it exists
+pub fn uid_of(name: &str) -> Option<u32> {
+    let name = CString::new(name).ok()?;
+    with_passwd(
+        // SAFETY: as in `lookup_name`, and `name` outlives the call
+        |entry, buffer, result| unsafe {
+            libc::getpwnam_r(name.as_ptr(), entry, buffer.as_mut_ptr(), buffer.len(), result)
+        },
+        |entry| entry.pw_uid,
+    )
+}
+
+/// A single user in a `--user` list
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum UserMatch {
+    Uid(u32),
+    Name(String),
+}
+
+impl UserMatch {
+    fn matches(&self, uid: u32, name: &str) -> bool {
+        match self {
+            UserMatch::Uid(expected) => *expected == uid,
+            UserMatch::Name(expected) => expected == name,
+        }
+    }
+}
+
+/// Which users to show, parsed from a list like `alice,1000,!root`
+#[derive(Debug, Clone, Default)]
+pub struct UserFilter {
+    include: Vec<UserMatch>,
+    exclude: Vec<UserMatch>,
+}
+
+impl UserFilter {
+    /// Parse a comma-separated list of user names or numeric UIDs,
+    /// where a leading `!` excludes that user instead
+    pub fn parse(spec: &str) -> Self {
+        let mut filter = Self::default();
+
+        for entry in spec.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (negated, user) = match entry.strip_prefix('!') {
+                Some(user) => (true, user.trim()),
+                None => (false, entry),
+            };
+            let user = match user.parse() {
+                Ok(uid) => UserMatch::Uid(uid),
+                Err(_) => UserMatch::Name(user.to_string()),
+            };
+
+            if negated {
+                filter.exclude.push(user);
+            } else {
+                filter.include.push(user);
+            }
+        }
+
+        filter
+    }
+
+    /// Whether a process owned by `uid`, called `name` in passwd, passes the filter
+    pub fn matches(&self, uid: u32, name: &str) -> bool {
+        (self.include.is_empty() || self.include.iter().any(|user| user.matches(uid, name)))
+            && !self.exclude.iter().any(|user| user.matches(uid, name))
+    }
+
+    /// Names in the filter that have no passwd entry and so can never match
+    pub fn unknown_names(&self) -> Vec<&str> {
+        self.include
+            .iter()
+            .chain(&self.exclude)
+            .filter_map(|user| match user {
+                UserMatch::Name(name) if uid_of(name).is_none() => Some(name.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+}